@@ -3,7 +3,7 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
-use winit_blit::{NativeFormat, PixelBufferTyped};
+use winit_blit::{NativeFormat, PixelBufferTyped, ResizeContents};
 
 fn main() {
     let event_loop = EventLoop::new();
@@ -13,6 +13,9 @@ fn main() {
         .build(&event_loop)
         .unwrap();
 
+    let (width, height): (u32, u32) = window.inner_size().into();
+    let mut buffer = PixelBufferTyped::<NativeFormat>::new_supported(width, height, &window);
+
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             event: WindowEvent::CloseRequested,
@@ -21,12 +24,14 @@ fn main() {
         Event::RedrawRequested(window_id) => {
             if window_id == window.id() {
                 let (width, height): (u32, u32) = window.inner_size().into();
-                let mut buffer =
-                    PixelBufferTyped::<NativeFormat>::new_supported(width, height, &window);
+                // Every pixel gets redrawn, so there's no point in keeping the old ones.
+                buffer
+                    .resize(width, height, ResizeContents::Undefined)
+                    .unwrap();
 
                 for (i, row) in buffer.rows_mut().enumerate() {
                     let value = (i % 256) as u16;
-                    for (j, pixel) in row.into_iter().enumerate() {
+                    for (j, pixel) in row.iter_mut().enumerate() {
                         let value = value * (j % 256) as u16 / 256;
                         *pixel = NativeFormat::from_rgb(
                             (256 * value / 256) as u8,
@@ -3,7 +3,7 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
-use winit_blit::{PixelBufferTyped, BGRA};
+use winit_blit::{PixelBufferTyped, ResizeContents, BGRA};
 
 fn main() {
     let event_loop = EventLoop::new();
@@ -18,6 +18,8 @@ fn main() {
     let blue = BGRA::from_rgb(0, 0, 255);
     let alpha = BGRA::new(0, 0, 0, 255);
     let mut blend_mode = BlendMode::Approx;
+    let (width, height): (u32, u32) = window.inner_size().into();
+    let mut buffer = PixelBufferTyped::<BGRA>::new_supported(width, height, &window);
     println!("blend mode = {:?}", blend_mode);
     event_loop.run(move |event, _, control_flow| {
         // println!("{:?}", event);
@@ -50,8 +52,9 @@ fn main() {
             Event::RedrawRequested(window_id) => {
                 if window_id == window.id() {
                     let (width, height): (u32, u32) = window.inner_size().into();
-                    let mut buffer =
-                        PixelBufferTyped::<BGRA>::new_supported(width, height, &window);
+                    buffer
+                        .resize(width, height, ResizeContents::Undefined)
+                        .unwrap();
                     let start = std::time::Instant::now();
 
                     let blend_fn = match blend_mode {
@@ -86,7 +89,7 @@ fn main() {
                             let y = ((i as f32 / height as f32) * 255.0).round() as u8;
                             let t_blend = blend_fn(y, red, green);
                             let b_blend = blend_fn(y, alpha, blue);
-                            for (j, pixel) in row.into_iter().enumerate() {
+                            for (j, pixel) in row.iter_mut().enumerate() {
                                 let x = ((j as f32 / width as f32) * 255.0).round() as u8;
                                 *pixel = blend_fn(x, t_blend, b_blend);
                             }
@@ -0,0 +1,512 @@
+//! Dispatch between the native platform backend and the in-memory backend.
+//!
+//! Both backends only provide access to their raw bytes, stored top-down; row access is
+//! implemented once, here.
+
+use crate::{
+    memory, platform_impl,
+    rect::{clip_blit, BlitArea, Rect, SourceBounds},
+    scale::ScaleFilter,
+    target::Target,
+    BlitError, PixelBufferFormatType, ResizeContents,
+};
+use std::{iter::FusedIterator, slice};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+pub(crate) enum Backend {
+    Native(platform_impl::PixelBuffer),
+    Memory(memory::PixelBuffer),
+}
+
+/// Checks that `len` bytes are enough for an image of the given size and format, with rows
+/// `stride` bytes apart, and returns the image's layout.
+pub(crate) fn slice_layout(
+    len: usize,
+    width: u32,
+    height: u32,
+    stride: usize,
+    format: PixelBufferFormatType,
+) -> Result<memory::Layout, BlitError> {
+    check_dimensions(width, height)?;
+    let layout = memory::Layout {
+        stride,
+        ..memory::Layout::new(width, height, format)
+    };
+    let pixel_len = width as usize * layout.bytes_per_pixel;
+    if stride < pixel_len {
+        return Err(BlitError::SliceTooSmall);
+    }
+    let required = match height {
+        0 => Some(0),
+        _ => stride
+            .checked_mul(height as usize - 1)
+            .and_then(|len| len.checked_add(pixel_len)),
+    };
+    match required {
+        Some(required) if required <= len => Ok(layout),
+        _ => Err(BlitError::SliceTooSmall),
+    }
+}
+
+/// Checks that a buffer of the given size can be addressed on every platform.
+fn check_dimensions(width: u32, height: u32) -> Result<(), BlitError> {
+    // Every platform addresses pixels with signed 32-bit integers.
+    if width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return Err(BlitError::DimensionsTooLarge);
+    }
+    (width as usize)
+        .checked_mul(4)
+        .and_then(|row_len| row_len.checked_mul(height as usize))
+        .ok_or(BlitError::DimensionsTooLarge)?;
+    Ok(())
+}
+
+/// Multiplies the color channels of each 4-byte pixel in `row` by its alpha channel.
+fn premultiply_row(row: &mut [u8]) {
+    for pixel in row.chunks_exact_mut(4) {
+        let alpha = u16::from(pixel[3]);
+        for channel in &mut pixel[..3] {
+            *channel = ((u16::from(*channel) * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($self:expr, $p:ident => $e:expr) => {
+        match $self {
+            Backend::Native($p) => $e,
+            Backend::Memory($p) => $e,
+        }
+    };
+}
+
+impl Backend {
+    pub fn new(
+        width: u32,
+        height: u32,
+        format: PixelBufferFormatType,
+        target: Target<'_>,
+    ) -> Result<Backend, BlitError> {
+        check_dimensions(width, height)?;
+
+        match target {
+            Target::Window(handle) => unsafe {
+                platform_impl::PixelBuffer::new(width, height, format, handle).map(Backend::Native)
+            },
+            Target::Memory(target) => {
+                if format != target.format() {
+                    return Err(BlitError::FormatNotSupported);
+                }
+                Ok(Backend::Memory(memory::PixelBuffer::new(
+                    width,
+                    height,
+                    format,
+                    target.id(),
+                )))
+            }
+        }
+    }
+
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+        contents: ResizeContents,
+    ) -> Result<(), BlitError> {
+        let (old_height, old_pixel_len) = (self.height(), self.pixel_len());
+        let (capacity_width, capacity_height) = dispatch!(self, p => p.capacity());
+
+        if width <= capacity_width && height <= capacity_height {
+            self.set_size(width, height)?;
+        } else {
+            // Allocate enough for the old size too, so that shrinking back doesn't reallocate.
+            let capacity = (width.max(capacity_width), height.max(capacity_height));
+            check_dimensions(capacity.0, capacity.1)?;
+            let saved: Vec<u8> = match contents {
+                ResizeContents::Preserve => self.rows().flatten().copied().collect(),
+                _ => Vec::new(),
+            };
+            match self {
+                Backend::Native(p) => unsafe { p.reallocate(capacity.0, capacity.1)? },
+                Backend::Memory(p) => p.reallocate(capacity.0, capacity.1),
+            }
+            if capacity != (width, height) {
+                self.set_size(width, height)?;
+            }
+            for (row, saved) in self.rows_mut().zip(saved.chunks(old_pixel_len.max(1))) {
+                let len = row.len().min(saved.len());
+                row[..len].copy_from_slice(&saved[..len]);
+            }
+        }
+
+        match contents {
+            ResizeContents::Preserve => {
+                for (y, row) in self.rows_mut().enumerate() {
+                    let start = match y < old_height as usize {
+                        true => old_pixel_len.min(row.len()),
+                        false => 0,
+                    };
+                    row[start..].fill(0);
+                }
+            }
+            ResizeContents::Clear => self.rows_mut().for_each(|row| row.fill(0)),
+            ResizeContents::Undefined => {}
+        }
+        Ok(())
+    }
+
+    /// Changes the size of the buffer without reallocating it. The size must fit in the
+    /// buffer's capacity.
+    fn set_size(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        match self {
+            Backend::Native(p) => p.set_size(width, height),
+            Backend::Memory(p) => {
+                p.set_size(width, height);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn blit(&self, target: Target<'_>) -> Result<(), BlitError> {
+        let src = Rect::from_size(self.width(), self.height());
+        self.blit_rect(src, (0, 0), SourceBounds::Clip, target)
+    }
+
+    pub fn blit_rect(
+        &self,
+        src: Rect,
+        dst_pos: (i32, i32),
+        bounds: SourceBounds,
+        target: Target<'_>,
+    ) -> Result<(), BlitError> {
+        let buffer_size = (self.width(), self.height());
+        if bounds == SourceBounds::Reject
+            && !Rect::from_size(buffer_size.0, buffer_size.1).contains_rect(&src)
+        {
+            return Err(BlitError::OutOfBounds);
+        }
+
+        let window_size = self.window_size(target)?;
+        match clip_blit(src, dst_pos, buffer_size, window_size) {
+            Some(area) => self.present(&[area], target),
+            None => Ok(()),
+        }
+    }
+
+    /// Blits each of `rects` onto the same position in the target, as a single update where the
+    /// platform supports it. The rectangles get clipped to the buffer's and the target's bounds.
+    pub fn blit_rects(&self, rects: &[Rect], target: Target<'_>) -> Result<(), BlitError> {
+        let buffer_size = (self.width(), self.height());
+        let window_size = self.window_size(target)?;
+        let areas: Vec<BlitArea> = rects
+            .iter()
+            .filter_map(|rect| clip_blit(*rect, (rect.x, rect.y), buffer_size, window_size))
+            .collect();
+        match areas.is_empty() {
+            true => Ok(()),
+            false => self.present(&areas, target),
+        }
+    }
+
+    /// Blits `src` onto `target`, scaled to fill `dst`, if the platform can scale blits itself.
+    ///
+    /// Returns `false` if the pixels need to be scaled in software instead.
+    pub fn blit_scaled(
+        &self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        target: Target<'_>,
+    ) -> Result<bool, BlitError> {
+        match (self, target) {
+            (Backend::Native(p), Target::Window(handle)) => unsafe {
+                p.blit_scaled(src, dst, filter, handle)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Presents the image in `data`, which has the buffer's format, onto the top-left corner of
+    /// `target` without copying it into a buffer.
+    ///
+    /// Returns `false` if the platform can't present the image directly.
+    pub fn blit_slice(
+        &self,
+        data: &[u8],
+        layout: memory::Layout,
+        target: Target<'_>,
+    ) -> Result<bool, BlitError> {
+        let window_size = self.window_size(target)?;
+        let size = (
+            layout.width.min(window_size.0),
+            layout.height.min(window_size.1),
+        );
+        if size.0 == 0 || size.1 == 0 {
+            return Ok(true);
+        }
+        match (self, target) {
+            (Backend::Native(p), Target::Window(handle)) => unsafe {
+                p.blit_slice(data, layout, size, handle)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Blits the whole of `staging`, which has the buffer's format, onto `dst_pos` in `target`.
+    ///
+    /// Wayland presents the staging buffer's pixels through the buffer's own surface, which would
+    /// otherwise take on the staging buffer's size.
+    pub fn blit_staging(
+        &self,
+        staging: &Backend,
+        dst_pos: (i32, i32),
+        target: Target<'_>,
+    ) -> Result<(), BlitError> {
+        let staging_size = (staging.width(), staging.height());
+        let src = Rect::from_size(staging_size.0, staging_size.1);
+        if let (Backend::Native(p), Target::Window(handle)) = (self, target) {
+            let window_size = self.window_size(target)?;
+            let (src_pos, dst_pos, size) = match clip_blit(src, dst_pos, staging_size, window_size)
+            {
+                Some(area) => area,
+                None => return Ok(()),
+            };
+            let layout = staging.layout();
+            if unsafe { p.blit_staged(staging.bytes(), layout, src_pos, dst_pos, size, handle)? } {
+                return Ok(());
+            }
+        }
+        staging.blit_rect(src, dst_pos, SourceBounds::Clip, target)
+    }
+
+    /// Makes the platform honor the alpha channel, which must be premultiplied, when presenting.
+    pub fn set_alpha(&mut self, alpha: bool) -> Result<(), BlitError> {
+        match self {
+            Backend::Native(p) => unsafe { p.set_alpha(alpha) },
+            // Memory targets receive the pixels as they are.
+            Backend::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Multiplies the color channels of every pixel by its alpha channel, which must be the
+    /// fourth byte.
+    pub fn premultiply(&mut self) {
+        self.rows_mut().for_each(premultiply_row);
+    }
+
+    /// Copies `src`'s pixels into the buffer, resizing it to match, and premultiplies them.
+    pub fn premultiply_from(&mut self, src: &Backend) -> Result<(), BlitError> {
+        self.resize(src.width(), src.height(), ResizeContents::Undefined)?;
+        for (dst, src) in self.rows_mut().zip(src.rows()) {
+            dst.copy_from_slice(src);
+            premultiply_row(dst);
+        }
+        Ok(())
+    }
+
+    /// Checks that `target` is the target the buffer was created for.
+    pub fn check_target(&self, target: Target<'_>) -> Result<(), BlitError> {
+        self.window_size(target).map(drop)
+    }
+
+    /// Copies the image in `data`, which has the buffer's format and size, into the buffer.
+    pub fn copy_from_slice(&mut self, data: &[u8], layout: memory::Layout) {
+        for (row, src) in self.rows_mut().zip(data.chunks(layout.stride.max(1))) {
+            row.copy_from_slice(&src[..row.len()]);
+        }
+    }
+
+    /// The size of `target`, which must be the target the buffer was created for.
+    pub fn window_size(&self, target: Target<'_>) -> Result<(u32, u32), BlitError> {
+        match (self, target) {
+            (Backend::Native(p), Target::Window(handle)) => unsafe { p.window_size(handle) },
+            (Backend::Memory(p), Target::Memory(target)) if p.target_id() == target.id() => {
+                Ok(target.size())
+            }
+            _ => Err(BlitError::WindowMismatch),
+        }
+    }
+
+    /// Presents already clipped blits onto `target`.
+    fn present(&self, areas: &[BlitArea], target: Target<'_>) -> Result<(), BlitError> {
+        match (self, target) {
+            (Backend::Native(p), Target::Window(handle)) => unsafe {
+                match areas {
+                    &[(src_pos, dst_pos, size)] => p.blit_rect(src_pos, dst_pos, size, handle),
+                    _ => p.blit_rects(areas, handle),
+                }
+            },
+            (Backend::Memory(p), Target::Memory(target)) => {
+                for &(src_pos, dst_pos, size) in areas {
+                    target.present(p, src_pos, dst_pos, size)?;
+                }
+                Ok(())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        dispatch!(self, p => p.bits_per_pixel())
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel() / 8
+    }
+
+    pub fn width(&self) -> u32 {
+        dispatch!(self, p => p.width())
+    }
+
+    pub fn row_len(&self) -> usize {
+        dispatch!(self, p => p.row_len())
+    }
+
+    pub fn height(&self) -> u32 {
+        dispatch!(self, p => p.height())
+    }
+
+    /// The layout of the buffer's memory.
+    fn layout(&self) -> memory::Layout {
+        memory::Layout {
+            width: self.width(),
+            height: self.height(),
+            stride: self.row_len(),
+            bytes_per_pixel: self.bytes_per_pixel(),
+        }
+    }
+
+    /// The buffer's memory: `height` rows, each `row_len` bytes long, from top to bottom.
+    pub fn bytes(&self) -> &[u8] {
+        dispatch!(self, p => p.bytes())
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        dispatch!(self, p => p.bytes_mut())
+    }
+
+    /// The length, in bytes, of the pixels in a row, excluding any padding.
+    fn pixel_len(&self) -> usize {
+        self.width() as usize * self.bytes_per_pixel()
+    }
+
+    /// The stride to chunk the bytes with. Never zero, so that chunking doesn't panic.
+    fn chunk_len(&self) -> usize {
+        match self.row_len() {
+            0 => 1,
+            l => l,
+        }
+    }
+
+    fn row_index(&self, row: u32) -> Option<usize> {
+        if row >= self.height() {
+            return None;
+        }
+        Some(row as usize * self.row_len())
+    }
+
+    pub fn row(&self, row: u32) -> Option<&[u8]> {
+        let index = self.row_index(row)?;
+        let pixel_len = self.pixel_len();
+        self.bytes().get(index..index + pixel_len)
+    }
+
+    pub fn row_mut(&mut self, row: u32) -> Option<&mut [u8]> {
+        let index = self.row_index(row)?;
+        let pixel_len = self.pixel_len();
+        self.bytes_mut().get_mut(index..index + pixel_len)
+    }
+
+    pub fn rows(&self) -> Rows<'_> {
+        Rows {
+            chunks: self.bytes().chunks(self.chunk_len()),
+            pixel_len: self.pixel_len(),
+        }
+    }
+
+    pub fn rows_mut(&mut self) -> RowsMut<'_> {
+        let (chunk_len, pixel_len) = (self.chunk_len(), self.pixel_len());
+        RowsMut {
+            chunks: self.bytes_mut().chunks_mut(chunk_len),
+            pixel_len,
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_rows(&self) -> impl IndexedParallelIterator<Item = &[u8]> {
+        let pixel_len = self.pixel_len();
+        self.bytes()
+            .par_chunks(self.chunk_len())
+            .map(move |row| &row[..pixel_len])
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = &mut [u8]> {
+        let (chunk_len, pixel_len) = (self.chunk_len(), self.pixel_len());
+        self.bytes_mut()
+            .par_chunks_mut(chunk_len)
+            .map(move |row| &mut row[..pixel_len])
+    }
+}
+
+/// An iterator over the rows of a pixel buffer, from top to bottom.
+pub(crate) struct Rows<'a> {
+    chunks: slice::Chunks<'a, u8>,
+    pixel_len: usize,
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let pixel_len = self.pixel_len;
+        self.chunks.next().map(|row| &row[..pixel_len])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Rows<'a> {
+    fn next_back(&mut self) -> Option<&'a [u8]> {
+        let pixel_len = self.pixel_len;
+        self.chunks.next_back().map(|row| &row[..pixel_len])
+    }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
+impl FusedIterator for Rows<'_> {}
+
+/// A mutable iterator over the rows of a pixel buffer, from top to bottom.
+pub(crate) struct RowsMut<'a> {
+    chunks: slice::ChunksMut<'a, u8>,
+    pixel_len: usize,
+}
+
+impl<'a> Iterator for RowsMut<'a> {
+    type Item = &'a mut [u8];
+
+    fn next(&mut self) -> Option<&'a mut [u8]> {
+        let pixel_len = self.pixel_len;
+        self.chunks.next().map(move |row| &mut row[..pixel_len])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for RowsMut<'a> {
+    fn next_back(&mut self) -> Option<&'a mut [u8]> {
+        let pixel_len = self.pixel_len;
+        self.chunks
+            .next_back()
+            .map(move |row| &mut row[..pixel_len])
+    }
+}
+
+impl ExactSizeIterator for RowsMut<'_> {}
+impl FusedIterator for RowsMut<'_> {}
@@ -0,0 +1,531 @@
+//! Checks that every backend we can read pixels back from blits exactly the requested
+//! rectangle.
+//!
+//! Every harness runs unconditionally, except for the X11 one, which is ignored by default since
+//! it needs an X server. Setting one up panics rather than skipping its tests, so a broken backend
+//! can't pass them without drawing anything.
+//!
+//! Wayland has no harness. A Wayland surface always takes the size of the buffer attached to it,
+//! and the core protocol has no way of reading back what the compositor shows, so there's no
+//! window to check the pixels around the destination rectangle of. The Wayland backend's own
+//! tests check the contents of the buffers it attaches instead.
+
+use crate::{BlitTarget, HeadlessWindow, PixelBufferFormatType, PixelBufferTyped, BGRA};
+
+/// A blit target whose pixels can be read back after blitting.
+trait Harness: BlitTarget + Sized {
+    /// Whether the target keeps the alpha channel of the pixels blitted onto it.
+    const ALPHA: bool = true;
+
+    /// Creates a `BGRA` target of the given size, with every pixel set to zero.
+    fn open(width: u32, height: u32) -> Self;
+
+    /// The `BGRA` bytes of the pixel at `(x, y)`, with an alpha of zero if the target has no
+    /// alpha channel.
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4];
+}
+
+impl Harness for HeadlessWindow {
+    fn open(width: u32, height: u32) -> HeadlessWindow {
+        HeadlessWindow::new(width, height, PixelBufferFormatType::BGRA)
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let row = self.row(y).unwrap();
+        let i = x as usize * 4;
+        [row[i], row[i + 1], row[i + 2], row[i + 3]]
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod fbdev {
+    use super::Harness;
+    use crate::{
+        target::{private::Sealed, Target},
+        BlitTarget, Framebuffer, FramebufferInfo, PixelBufferFormatType,
+    };
+    use std::{fs, path::PathBuf};
+
+    /// A framebuffer backed by a temporary file, which gets read back after each blit.
+    pub struct FileFramebuffer {
+        framebuffer: Option<Framebuffer>,
+        path: PathBuf,
+        line_length: usize,
+    }
+
+    impl Sealed for FileFramebuffer {
+        fn target(&self) -> Target<'_> {
+            self.framebuffer.as_ref().unwrap().target()
+        }
+    }
+    impl BlitTarget for FileFramebuffer {}
+
+    impl Harness for FileFramebuffer {
+        fn open(width: u32, height: u32) -> FileFramebuffer {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "winit-blit-conformance-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            // Pad the rows, to catch blits that ignore the framebuffer's stride.
+            let line_length = width as usize * 4 + 12;
+            fs::write(&path, vec![0; line_length * height as usize]).unwrap();
+            let info = FramebufferInfo {
+                width,
+                height,
+                line_length,
+                format: PixelBufferFormatType::BGRA,
+            };
+            FileFramebuffer {
+                framebuffer: Some(Framebuffer::with_info(&path, info).unwrap()),
+                path,
+                line_length,
+            }
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+            let contents = fs::read(&self.path).unwrap();
+            let i = y as usize * self.line_length + x as usize * 4;
+            [
+                contents[i],
+                contents[i + 1],
+                contents[i + 2],
+                contents[i + 3],
+            ]
+        }
+    }
+
+    impl Drop for FileFramebuffer {
+        fn drop(&mut self) {
+            self.framebuffer = None;
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::Harness;
+    use raw_window_handle::{windows::WindowsHandle, HasRawWindowHandle, RawWindowHandle};
+    use std::ptr;
+    use winapi::{
+        shared::windef::HWND,
+        um::{wingdi, winuser},
+    };
+
+    /// A borderless, topmost window in the corner of the screen, whose pixels get read back with
+    /// `GetPixel`.
+    ///
+    /// The window has to be shown, since GDI discards everything drawn onto hidden windows. It's
+    /// never activated, and never gets to process any messages, so nothing but the blits draws
+    /// onto it.
+    pub struct TestWindow {
+        hwnd: HWND,
+    }
+
+    unsafe impl HasRawWindowHandle for TestWindow {
+        fn raw_window_handle(&self) -> RawWindowHandle {
+            RawWindowHandle::Windows(WindowsHandle {
+                hwnd: self.hwnd as _,
+                ..WindowsHandle::empty()
+            })
+        }
+    }
+
+    impl Harness for TestWindow {
+        const ALPHA: bool = false;
+
+        fn open(width: u32, height: u32) -> TestWindow {
+            // The predefined static control class, so that no class needs registering.
+            let class: Vec<u16> = "STATIC\0".encode_utf16().collect();
+            unsafe {
+                let hwnd = winuser::CreateWindowExW(
+                    winuser::WS_EX_TOPMOST | winuser::WS_EX_TOOLWINDOW | winuser::WS_EX_NOACTIVATE,
+                    class.as_ptr(),
+                    ptr::null(),
+                    winuser::WS_POPUP,
+                    0,
+                    0,
+                    width as _,
+                    height as _,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                );
+                assert!(!hwnd.is_null(), "failed to create a test window");
+                winuser::ShowWindow(hwnd, winuser::SW_SHOWNOACTIVATE);
+                // Showing the window erased it with the control's background color.
+                let hdc = winuser::GetDC(hwnd);
+                wingdi::PatBlt(hdc, 0, 0, width as _, height as _, wingdi::BLACKNESS);
+                winuser::ReleaseDC(hwnd, hdc);
+                winuser::ValidateRect(hwnd, ptr::null());
+                TestWindow { hwnd }
+            }
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+            unsafe {
+                let hdc = winuser::GetDC(self.hwnd);
+                let color = wingdi::GetPixel(hdc, x as _, y as _);
+                winuser::ReleaseDC(self.hwnd, hdc);
+                assert_ne!(wingdi::CLR_INVALID, color, "pixel outside of the window");
+                // `COLORREF`s are laid out as `0x00bbggrr`.
+                let [r, g, b, _] = color.to_le_bytes();
+                [b, g, r, 0]
+            }
+        }
+    }
+
+    impl Drop for TestWindow {
+        fn drop(&mut self) {
+            unsafe {
+                winuser::DestroyWindow(self.hwnd);
+            }
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+mod x11 {
+    use super::Harness;
+    use raw_window_handle::{unix::XlibHandle, HasRawWindowHandle, RawWindowHandle};
+    use std::{mem, ptr};
+    use x11_dl::xlib::{self, Display, Xlib};
+
+    /// An override-redirect window in the corner of the default screen, whose pixels get read
+    /// back with `XGetImage`.
+    ///
+    /// Run the tests using it with `cargo test -- --ignored`, under `xvfb-run` on a machine
+    /// without a display.
+    pub struct TestWindow {
+        xlib: Xlib,
+        display: *mut Display,
+        window: xlib::Window,
+    }
+
+    unsafe impl HasRawWindowHandle for TestWindow {
+        fn raw_window_handle(&self) -> RawWindowHandle {
+            RawWindowHandle::Xlib(XlibHandle {
+                window: self.window,
+                display: self.display as _,
+                ..XlibHandle::empty()
+            })
+        }
+    }
+
+    impl Harness for TestWindow {
+        const ALPHA: bool = false;
+
+        fn open(width: u32, height: u32) -> TestWindow {
+            let xlib = Xlib::open().expect("libX11 isn't installed");
+            unsafe {
+                let display = (xlib.XOpenDisplay)(ptr::null());
+                assert!(!display.is_null(), "no X server available");
+                let screen = (xlib.XDefaultScreen)(display);
+                let root = (xlib.XRootWindow)(display, screen);
+                let black = (xlib.XBlackPixel)(display, screen);
+                let window =
+                    (xlib.XCreateSimpleWindow)(display, root, 0, 0, width, height, 0, black, black);
+                // Keep window managers from decorating or moving the window.
+                let mut attributes: xlib::XSetWindowAttributes = mem::zeroed();
+                attributes.override_redirect = xlib::True;
+                (xlib.XChangeWindowAttributes)(
+                    display,
+                    window,
+                    xlib::CWOverrideRedirect,
+                    &mut attributes,
+                );
+                // The window is only guaranteed to have been drawn with its background once it
+                // gets exposed.
+                (xlib.XSelectInput)(display, window, xlib::ExposureMask);
+                (xlib.XMapWindow)(display, window);
+                let mut event = mem::zeroed();
+                (xlib.XWindowEvent)(display, window, xlib::ExposureMask, &mut event);
+                TestWindow {
+                    xlib,
+                    display,
+                    window,
+                }
+            }
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+            unsafe {
+                (self.xlib.XSync)(self.display, xlib::False);
+                let image = (self.xlib.XGetImage)(
+                    self.display,
+                    self.window,
+                    x as _,
+                    y as _,
+                    1,
+                    1,
+                    !0,
+                    xlib::ZPixmap,
+                );
+                assert!(!image.is_null(), "pixel outside of the window");
+                let pixel = (self.xlib.XGetPixel)(image, 0, 0);
+                (self.xlib.XDestroyImage)(image);
+                // Depth 24 pixels are laid out as `0x00rrggbb`.
+                let [b, g, r, _] = (pixel as u32).to_le_bytes();
+                [b, g, r, 0]
+            }
+        }
+    }
+
+    impl Drop for TestWindow {
+        fn drop(&mut self) {
+            unsafe {
+                (self.xlib.XDestroyWindow)(self.display, self.window);
+                (self.xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+}
+
+mod vnc {
+    use super::Harness;
+    use crate::{
+        target::{private::Sealed, Target},
+        BlitTarget, PixelBufferFormatType, VncServer,
+    };
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
+
+    /// A VNC server with a client connected, which reads pixels back by requesting raw updates
+    /// of them in the server's default pixel format.
+    pub struct TestServer {
+        server: VncServer,
+        stream: TcpStream,
+    }
+
+    impl Sealed for TestServer {
+        fn target(&self) -> Target<'_> {
+            self.server.target()
+        }
+    }
+    impl BlitTarget for TestServer {}
+
+    impl Harness for TestServer {
+        const ALPHA: bool = false;
+
+        fn open(width: u32, height: u32) -> TestServer {
+            let format = PixelBufferFormatType::BGRA;
+            let server = VncServer::bind("127.0.0.1:0", width, height, format).unwrap();
+            let (stream, _) = server.connect_client();
+            TestServer { server, stream }
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+            let mut stream = &self.stream;
+            let [x0, x1] = (x as u16).to_be_bytes();
+            let [y0, y1] = (y as u16).to_be_bytes();
+            stream
+                .write_all(&[3, 0, x0, x1, y0, y1, 0, 1, 0, 1])
+                .unwrap();
+            // A `FramebufferUpdate` with a single raw rectangle, holding a single pixel.
+            let mut update = [0; 16 + 4];
+            stream.read_exact(&mut update).unwrap();
+            assert_eq!(&[0, 0, 0, 1, x0, x1, y0, y1, 0, 1, 0, 1], &update[..12]);
+            // The default format is little-endian `0x00rrggbb`.
+            [update[16], update[17], update[18], 0]
+        }
+    }
+}
+
+mod terminal {
+    use super::Harness;
+    use crate::{PixelBufferFormatType, Terminal, TerminalMode};
+    use std::collections::HashMap;
+
+    /// Kitty's output is lossless, so the harness replays it onto an image of its own.
+    pub type TestTerminal = Terminal<Vec<u8>>;
+
+    impl Harness for TestTerminal {
+        const ALPHA: bool = false;
+
+        fn open(width: u32, height: u32) -> TestTerminal {
+            let format = PixelBufferFormatType::BGRA;
+            Terminal::new(Vec::new(), width, height, format, TerminalMode::Kitty)
+        }
+
+        fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+            let output = self.get_ref();
+            let output = std::str::from_utf8(&output).unwrap();
+            // The terminal's background shows until the first blit transmits the image.
+            let width = self.width() as usize;
+            let mut image = vec![0; width * self.height() as usize * 3];
+
+            let (mut control, mut payload) = (String::new(), String::new());
+            for command in output.split("\x1b_G").skip(1) {
+                let command = command.split("\x1b\\").next().unwrap();
+                let (keys, data) = command.split_once(';').unwrap();
+                if control.is_empty() {
+                    control = keys.to_owned();
+                }
+                payload.push_str(data);
+                if keys.ends_with("m=1") {
+                    continue;
+                }
+
+                let keys: HashMap<&str, u32> = control
+                    .split(',')
+                    .filter_map(|key| key.split_once('='))
+                    .filter_map(|(key, value)| Some((key, value.parse().ok()?)))
+                    .collect();
+                assert_eq!(24, keys["f"], "only RGB images are supported");
+                // Transmissions carry the whole image, while frames replace a rectangle of it.
+                let pos = match control.starts_with("a=T") {
+                    true => (0, 0),
+                    false => (keys["x"], keys["y"]),
+                };
+                let size = (keys["s"], keys["v"]);
+                let rgb = decode_base64(&payload);
+                for (row, bytes) in rgb.chunks(size.0 as usize * 3).enumerate() {
+                    let start = ((pos.1 as usize + row) * width + pos.0 as usize) * 3;
+                    image[start..start + bytes.len()].copy_from_slice(bytes);
+                }
+                control.clear();
+                payload.clear();
+            }
+
+            let i = (y as usize * width + x as usize) * 3;
+            [image[i + 2], image[i + 1], image[i], 0]
+        }
+    }
+
+    fn decode_base64(encoded: &str) -> Vec<u8> {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut bytes = Vec::new();
+        for chunk in encoded.as_bytes().chunks(4) {
+            let digits: Vec<u32> = chunk
+                .iter()
+                .take_while(|&&c| c != b'=')
+                .map(|&c| ALPHABET.iter().position(|&a| a == c).unwrap() as u32)
+                .collect();
+            let n = digits
+                .iter()
+                .enumerate()
+                .fold(0, |n, (i, &digit)| n | digit << (18 - i * 6));
+            bytes.extend_from_slice(&n.to_be_bytes()[1..digits.len()]);
+        }
+        bytes
+    }
+}
+
+/// A pixel that's unique to its position in the source buffer, and never zero.
+fn source_pixel(x: u32, y: u32) -> BGRA {
+    BGRA::new(x as u8, y as u8, 0x80, 0xff)
+}
+
+/// Blits `size` pixels from `src_pos` to `dst_pos`, then checks that exactly the pixels in the
+/// destination rectangle changed, to the right values.
+fn check_blit_rect<H: Harness>(src_pos: (u32, u32), dst_pos: (u32, u32), size: (u32, u32)) {
+    let (width, height) = (7, 5);
+    let target = H::open(width, height);
+    let mut pb = PixelBufferTyped::<BGRA>::new(6, 4, &target).unwrap();
+    for (y, row) in pb.rows_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = source_pixel(x as u32, y as u32);
+        }
+    }
+    pb.blit_rect(src_pos, dst_pos, size, &target).unwrap();
+
+    for y in 0..height {
+        for x in 0..width {
+            let inside = (dst_pos.0..dst_pos.0 + size.0).contains(&x)
+                && (dst_pos.1..dst_pos.1 + size.1).contains(&y);
+            let expected = match inside {
+                true => {
+                    let p = source_pixel(x - dst_pos.0 + src_pos.0, y - dst_pos.1 + src_pos.1);
+                    [p.b, p.g, p.r, if H::ALPHA { p.a } else { 0 }]
+                }
+                false => [0; 4],
+            };
+            assert_eq!(
+                expected,
+                target.pixel(x, y),
+                "pixel ({}, {}) after blitting {:?} from {:?} to {:?}",
+                x,
+                y,
+                size,
+                src_pos,
+                dst_pos
+            );
+        }
+    }
+}
+
+macro_rules! conformance_tests {
+    ($($(#[$attr:meta])* $name:ident: $harness:ty),+ $(,)?) => {$(
+        mod $name {
+            use super::*;
+
+            #[test]
+            $(#[$attr])*
+            fn blit_rect_origin() {
+                check_blit_rect::<$harness>((0, 0), (0, 0), (6, 4));
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn blit_rect_src_offset() {
+                check_blit_rect::<$harness>((2, 1), (0, 0), (3, 2));
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn blit_rect_dst_offset() {
+                check_blit_rect::<$harness>((0, 0), (3, 2), (2, 3));
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn blit_rect_both_offsets() {
+                check_blit_rect::<$harness>((4, 1), (1, 3), (2, 2));
+            }
+
+            #[test]
+            $(#[$attr])*
+            fn blit_rect_empty() {
+                check_blit_rect::<$harness>((1, 1), (2, 2), (0, 3));
+            }
+        }
+    )+};
+}
+
+conformance_tests!(
+    headless: HeadlessWindow,
+    vnc_server: vnc::TestServer,
+    kitty_terminal: terminal::TestTerminal,
+);
+#[cfg(target_os = "linux")]
+conformance_tests!(framebuffer: fbdev::FileFramebuffer);
+// The test windows would cover each other, and throw off the GDI object counts of the Windows
+// backend's own tests.
+#[cfg(windows)]
+conformance_tests!(#[serial_test::serial] window: windows::TestWindow);
+// Like the Windows ones, the test windows would cover each other.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+conformance_tests!(
+    #[ignore = "needs an X server"]
+    #[serial_test::serial]
+    x11_window: x11::TestWindow,
+);
@@ -0,0 +1,101 @@
+//! Tracking of the parts of a pixel buffer that changed since it was last presented.
+
+use crate::rect::Rect;
+
+/// Above this many rectangles, the damage collapses into a single bounding rectangle. Presenting
+/// each rectangle has a fixed cost, which eventually outweighs the cost of the extra pixels.
+const MAX_RECTS: usize = 16;
+
+/// A list of rectangles covering every changed pixel.
+#[derive(Debug, Default)]
+pub(crate) struct Damage {
+    rects: Vec<Rect>,
+}
+
+fn area(rect: &Rect) -> u64 {
+    u64::from(rect.width) * u64::from(rect.height)
+}
+
+impl Damage {
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    /// Adds `rect` to the damage, merging it with existing rectangles wherever the merged
+    /// rectangle wouldn't cover more pixels than the two separate ones.
+    pub fn add(&mut self, mut rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        while let Some(i) = self
+            .rects
+            .iter()
+            .position(|other| area(&rect.union(other)) <= area(&rect) + area(other))
+        {
+            rect = rect.union(&self.rects.swap_remove(i));
+        }
+        self.rects.push(rect);
+
+        if self.rects.len() > MAX_RECTS {
+            let bounds = self.rects.iter().fold(rect, |a, b| a.union(b));
+            self.rects.clear();
+            self.rects.push(bounds);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadlessWindow, BGR};
+
+    #[test]
+    fn damage_coalesces() {
+        let mut damage = Damage::default();
+        // Consecutive rows merge into a single rectangle.
+        for y in 2..5 {
+            damage.add(Rect::new(0, y, 8, 1));
+        }
+        damage.add(Rect::new(1, 3, 2, 2));
+        assert_eq!(&[Rect::new(0, 2, 8, 3)][..], damage.rects());
+
+        // Distant rectangles stay separate.
+        damage.add(Rect::new(6, 8, 2, 2));
+        assert_eq!(2, damage.rects().len());
+
+        // Too many rectangles collapse into one.
+        for x in 0..MAX_RECTS as i32 - 1 {
+            damage.add(Rect::new(x * 2, 20, 1, 1));
+        }
+        assert_eq!(&[Rect::new(0, 2, 29, 19)][..], damage.rects());
+    }
+
+    #[test]
+    fn pixelbuffer_blit_damaged() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((4, 4), (4, 4));
+        assert!(pb.damage().is_empty());
+        pb.set_damage_tracking(true);
+        assert_eq!(&[Rect::from_size(4, 4)][..], pb.damage());
+        pb.blit_damaged(&window).unwrap();
+        assert!(pb.damage().is_empty());
+
+        for row in pb.region_rows_mut(Rect::new(-1, 1, 3, 2)) {
+            assert_eq!(2, row.len());
+            row.fill(BGR::from_rgb(9, 9, 9));
+        }
+        pb.row_mut(3).unwrap()[3] = BGR::from_rgb(7, 7, 7);
+        assert_eq!(
+            &[Rect::new(0, 1, 2, 2), Rect::new(0, 3, 4, 1)][..],
+            pb.damage()
+        );
+
+        pb.blit_damaged(&window).unwrap();
+        assert!(pb.damage().is_empty());
+        assert_eq!(vec![9, 9, 0, 0], window.first_bytes(2));
+        assert_eq!(vec![0, 0, 0, 7], window.first_bytes(3));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::{
+    memory::{self, Layout},
+    target::{self, MemoryTarget},
+    PixelBufferFormatType,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::{raw::c_ulong, unix::io::AsRawFd},
+    path::Path,
+    ptr,
+};
+
+/// A Linux framebuffer device, such as `/dev/fb0`.
+///
+/// Pixel buffers blitted onto a framebuffer get copied straight into the device's memory, which
+/// makes it possible to display them without any display server running.
+pub struct Framebuffer {
+    id: u64,
+    info: FramebufferInfo,
+    /// Kept open for as long as the device memory is mapped.
+    _file: File,
+    map: *mut u8,
+    map_len: usize,
+    /// The offset of the visible screen from the start of the mapping.
+    offset: usize,
+}
+
+unsafe impl Send for Framebuffer {}
+
+/// The geometry and pixel format of a framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FramebufferInfo {
+    /// The visible width of the framebuffer, in pixels.
+    pub width: u32,
+    /// The visible height of the framebuffer, in pixels.
+    pub height: u32,
+    /// The length, in bytes, of a single row of the framebuffer.
+    pub line_length: usize,
+    /// The layout of each pixel in the framebuffer.
+    pub format: PixelBufferFormatType,
+}
+
+// Definitions from `linux/fb.h`.
+const FBIOGET_VSCREENINFO: c_ulong = 0x4600;
+const FBIOGET_FSCREENINFO: c_ulong = 0x4602;
+
+#[repr(C)]
+#[derive(Default)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbFixScreeninfo {
+    id: [u8; 16],
+    smem_start: c_ulong,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: c_ulong,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+/// Picks the pixel format with the same memory layout as the framebuffer's pixels.
+fn format(var: &FbVarScreeninfo) -> Option<PixelBufferFormatType> {
+    let channels = (var.red.offset, var.green.offset, var.blue.offset);
+    let lengths = (var.red.length, var.green.length, var.blue.length);
+    if lengths != (8, 8, 8) {
+        return None;
+    }
+    match (var.bits_per_pixel, channels) {
+        (32, (16, 8, 0)) => Some(PixelBufferFormatType::BGRA),
+        (32, (0, 8, 16)) => Some(PixelBufferFormatType::RGBA),
+        (24, (16, 8, 0)) => Some(PixelBufferFormatType::BGR),
+        (24, (0, 8, 16)) => Some(PixelBufferFormatType::RGB),
+        _ => None,
+    }
+}
+
+impl Framebuffer {
+    /// Opens the framebuffer device at `path`, querying its geometry and pixel format from the
+    /// kernel.
+    ///
+    /// Returns an error if the framebuffer's pixel format doesn't correspond to one of the
+    /// [`PixelBufferFormatType`]s.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Framebuffer> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut var = FbVarScreeninfo::default();
+        let mut fix = FbFixScreeninfo::default();
+        unsafe {
+            if libc::ioctl(file.as_raw_fd(), FBIOGET_VSCREENINFO as _, &mut var) == -1
+                || libc::ioctl(file.as_raw_fd(), FBIOGET_FSCREENINFO as _, &mut fix) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let format = format(&var).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported framebuffer pixel format ({} bits per pixel)",
+                    var.bits_per_pixel
+                ),
+            )
+        })?;
+
+        let info = FramebufferInfo {
+            width: var.xres,
+            height: var.yres,
+            line_length: fix.line_length as usize,
+            format,
+        };
+        let bytes_per_pixel = var.bits_per_pixel as usize / 8;
+        let offset =
+            var.yoffset as usize * info.line_length + var.xoffset as usize * bytes_per_pixel;
+        Framebuffer::map(file, info, offset, fix.smem_len as usize)
+    }
+
+    /// Opens the file at `path` as a framebuffer with the given geometry and pixel format, rather
+    /// than querying it from the kernel.
+    ///
+    /// This works with any file that can be memory-mapped, which makes it possible to stand in a
+    /// regular file for a framebuffer device. Returns an error if the file is shorter than
+    /// `info.line_length * info.height` bytes.
+    pub fn with_info<P: AsRef<Path>>(path: P, info: FramebufferInfo) -> io::Result<Framebuffer> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len() as usize;
+        Framebuffer::map(file, info, 0, len)
+    }
+
+    fn map(
+        file: File,
+        info: FramebufferInfo,
+        offset: usize,
+        len: usize,
+    ) -> io::Result<Framebuffer> {
+        let layout = Layout::new(info.width, info.height, info.format);
+        // The geometry may come from the caller, so none of it can be trusted not to overflow.
+        let row_len = (layout.width as usize).checked_mul(layout.bytes_per_pixel);
+        let end = info
+            .line_length
+            .checked_mul(info.height as usize)
+            .and_then(|size| size.checked_add(offset));
+        let fits = match (row_len, end) {
+            (Some(row_len), Some(end)) => row_len <= info.line_length && end <= len,
+            _ => false,
+        };
+        if !fits {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Framebuffer memory is too small for its geometry",
+            ));
+        }
+
+        let map = match len {
+            0 => ptr::null_mut(),
+            _ => unsafe {
+                let map = libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    file.as_raw_fd(),
+                    0,
+                );
+                if map == libc::MAP_FAILED {
+                    return Err(io::Error::last_os_error());
+                }
+                map as *mut u8
+            },
+        };
+
+        Ok(Framebuffer {
+            id: target::next_id(),
+            info,
+            _file: file,
+            map,
+            map_len: len,
+            offset,
+        })
+    }
+
+    /// The geometry and pixel format of the framebuffer.
+    pub fn info(&self) -> FramebufferInfo {
+        self.info
+    }
+
+    /// The visible width, in pixels, of the framebuffer.
+    pub fn width(&self) -> u32 {
+        self.info.width
+    }
+
+    /// The visible height, in pixels, of the framebuffer.
+    pub fn height(&self) -> u32 {
+        self.info.height
+    }
+
+    fn layout(&self) -> Layout {
+        Layout {
+            stride: self.info.line_length,
+            ..Layout::new(self.info.width, self.info.height, self.info.format)
+        }
+    }
+}
+
+impl MemoryTarget for Framebuffer {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn format(&self) -> PixelBufferFormatType {
+        self.info.format
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.info.width, self.info.height)
+    }
+
+    fn present(
+        &self,
+        src: &memory::PixelBuffer,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<()> {
+        let layout = self.layout();
+        if self.map.is_null() || layout.len() == 0 {
+            return Ok(());
+        }
+        let screen =
+            unsafe { std::slice::from_raw_parts_mut(self.map.add(self.offset), layout.len()) };
+        memory::copy_rect(
+            src.bytes(),
+            src.layout(),
+            src_pos,
+            screen,
+            layout,
+            dst_pos,
+            size,
+        );
+        Ok(())
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        if !self.map.is_null() {
+            unsafe {
+                libc::munmap(self.map as *mut _, self.map_len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PixelBufferTyped, BGRA};
+    use std::{fs, io::Read};
+
+    fn temp_file(name: &str, len: usize) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("winit-blit-{}-{}", std::process::id(), name));
+        fs::write(&path, vec![0; len]).unwrap();
+        path
+    }
+
+    #[test]
+    fn framebuffer_blit_rect() {
+        // Each row has 8 bytes of padding, like many real framebuffers.
+        let info = FramebufferInfo {
+            width: 4,
+            height: 3,
+            line_length: 24,
+            format: PixelBufferFormatType::BGRA,
+        };
+        let path = temp_file("fb-blit", 24 * 3);
+        let fb = Framebuffer::with_info(&path, info).unwrap();
+
+        let mut pb = PixelBufferTyped::<BGRA>::new(2, 2, &fb).unwrap();
+        for row in pb.rows_mut() {
+            for pixel in row {
+                *pixel = BGRA::new(1, 2, 3, 4);
+            }
+        }
+        pb.blit_rect((0, 0), (3, 1), (2, 2), &fb).unwrap();
+        drop(fb);
+
+        let mut contents = Vec::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut expected = vec![0; 24 * 3];
+        expected[24 + 12..24 + 16].copy_from_slice(&[1, 2, 3, 4]);
+        expected[48 + 12..48 + 16].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(expected, contents);
+    }
+
+    #[test]
+    fn framebuffer_too_small() {
+        let info = FramebufferInfo {
+            width: 4,
+            height: 3,
+            line_length: 16,
+            format: PixelBufferFormatType::BGRA,
+        };
+        let path = temp_file("fb-small", 16 * 2);
+        let result = Framebuffer::with_info(&path, info);
+        // A geometry whose size overflows mustn't wrap around the check.
+        let huge = FramebufferInfo {
+            line_length: usize::MAX / 2 + 1,
+            height: 2,
+            ..info
+        };
+        let overflow = Framebuffer::with_info(&path, huge);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+        assert_eq!(io::ErrorKind::InvalidInput, overflow.err().unwrap().kind());
+    }
+
+    #[test]
+    fn framebuffer_formats() {
+        let channel = |offset| FbBitfield {
+            offset,
+            length: 8,
+            msb_right: 0,
+        };
+        let mut var = FbVarScreeninfo {
+            bits_per_pixel: 32,
+            red: channel(16),
+            green: channel(8),
+            blue: channel(0),
+            ..FbVarScreeninfo::default()
+        };
+        assert_eq!(Some(PixelBufferFormatType::BGRA), format(&var));
+
+        var.bits_per_pixel = 16;
+        var.red.length = 5;
+        assert_eq!(None, format(&var));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{
+    memory::{self, Layout},
+    target::{self, MemoryTarget},
+    PixelBufferFormatType,
+};
+use std::{
+    cell::{Ref, RefCell},
+    io,
+};
+
+/// A virtual window that lives entirely in memory.
+///
+/// Pixel buffers can be created for and blitted onto a headless window just like a native one,
+/// but blitting copies the pixels into the window's surface, where they can be inspected. This
+/// makes it possible to test rendering code, or render offscreen, without a display server.
+///
+/// The surface uses the same layout as pixel buffers: rows are ordered top-to-bottom, and each
+/// row is padded to a multiple of four bytes.
+#[derive(Debug)]
+pub struct HeadlessWindow {
+    id: u64,
+    format: PixelBufferFormatType,
+    layout: Layout,
+    surface: RefCell<Vec<u8>>,
+}
+
+impl HeadlessWindow {
+    /// Creates a new headless window with a surface of the given size and format.
+    ///
+    /// Only pixel buffers with the same format can be blitted onto the window. The surface's
+    /// pixels are all initialized to zero.
+    pub fn new(width: u32, height: u32, format: PixelBufferFormatType) -> HeadlessWindow {
+        let layout = Layout::new(width, height, format);
+        HeadlessWindow {
+            id: target::next_id(),
+            format,
+            layout,
+            surface: RefCell::new(vec![0; layout.len()]),
+        }
+    }
+
+    /// The width, in pixels, of the window's surface.
+    pub fn width(&self) -> u32 {
+        self.layout.width
+    }
+
+    /// The height, in pixels, of the window's surface.
+    pub fn height(&self) -> u32 {
+        self.layout.height
+    }
+
+    /// The format of the window's surface.
+    pub fn format(&self) -> PixelBufferFormatType {
+        self.format
+    }
+
+    /// The length, in bytes, of a single row of the window's surface.
+    pub fn row_len(&self) -> usize {
+        self.layout.stride
+    }
+
+    /// The raw bytes of the window's surface, including any row padding.
+    pub fn surface(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.surface.borrow(), |surface| &surface[..])
+    }
+
+    /// Gets the row of the window's surface at the particular height.
+    pub fn row(&self, row: u32) -> Option<Ref<'_, [u8]>> {
+        if row >= self.layout.height {
+            return None;
+        }
+        let index = row as usize * self.layout.stride;
+        let pixel_len = self.layout.width as usize * self.layout.bytes_per_pixel;
+        Ref::filter_map(self.surface.borrow(), |surface| {
+            surface.get(index..index + pixel_len)
+        })
+        .ok()
+    }
+}
+
+impl MemoryTarget for HeadlessWindow {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn format(&self) -> PixelBufferFormatType {
+        self.format
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.layout.width, self.layout.height)
+    }
+
+    fn present(
+        &self,
+        src: &memory::PixelBuffer,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<()> {
+        let mut surface = self.surface.borrow_mut();
+        memory::copy_rect(
+            src.bytes(),
+            src.layout(),
+            src_pos,
+            &mut surface,
+            self.layout,
+            dst_pos,
+            size,
+        );
+        Ok(())
+    }
+}
+
+/// Test helpers, shared by the tests of everything that gets blitted through a headless window.
+#[cfg(test)]
+impl HeadlessWindow {
+    /// A window of the given size in `P`'s format, along with a pixel buffer for it.
+    pub(crate) fn with_buffer<P: crate::PixelBufferFormat>(
+        (window_width, window_height): (u32, u32),
+        (width, height): (u32, u32),
+    ) -> (HeadlessWindow, crate::PixelBufferTyped<P>) {
+        let window = HeadlessWindow::new(window_width, window_height, P::FORMAT_TYPE);
+        let buffer = crate::PixelBufferTyped::new(width, height, &window).unwrap();
+        (window, buffer)
+    }
+
+    /// The first byte of each pixel in row `y`, which is enough to tell apart pixels whose
+    /// channels are all the same.
+    pub(crate) fn first_bytes(&self, y: u32) -> Vec<u8> {
+        let row = self.row(y).unwrap();
+        row.iter()
+            .step_by(self.layout.bytes_per_pixel)
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlitError, PixelBuffer, PixelBufferCreationError, BGR, BGRA};
+
+    #[test]
+    fn pixelbuffer_blit() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGRA>((4, 3), (4, 3));
+        for (y, row) in pb.rows_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = BGRA::new(x as u8, y as u8, 0, 255);
+            }
+        }
+        assert_eq!(&[0; 16][..], &*window.row(2).unwrap());
+
+        pb.blit(&window).unwrap();
+        assert_eq!(&[1, 2, 0, 255][..], &window.row(2).unwrap()[4..8]);
+        assert!(window.row(3).is_none());
+    }
+
+    #[test]
+    fn pixelbuffer_blit_rect_clips() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((3, 3), (5, 5));
+        for row in pb.rows_mut() {
+            row.fill(BGR::from_rgb(9, 9, 9));
+        }
+        pb.blit_rect((0, 0), (1, 2), (5, 5), &window).unwrap();
+
+        assert_eq!(12, window.row_len());
+        assert_eq!(vec![0; 3], window.first_bytes(1));
+        assert_eq!(vec![0, 9, 9], window.first_bytes(2));
+        // Row padding is never touched.
+        assert_eq!(&[0; 3][..], &window.surface()[33..36]);
+    }
+
+    #[test]
+    fn pixelbuffer_format_mismatch() {
+        let window = HeadlessWindow::new(3, 3, PixelBufferFormatType::BGR);
+        match PixelBuffer::new(3, 3, PixelBufferFormatType::RGB, &window) {
+            Err(PixelBufferCreationError::FormatNotSupported) => (),
+            _ => panic!("expected FormatNotSupported"),
+        }
+    }
+
+    #[test]
+    fn pixelbuffer_window_mismatch() {
+        let (_, pb) = HeadlessWindow::with_buffer::<BGRA>((3, 3), (3, 3));
+        let other = HeadlessWindow::new(3, 3, PixelBufferFormatType::BGRA);
+        match pb.blit(&other) {
+            Err(BlitError::WindowMismatch) => (),
+            r => panic!("expected WindowMismatch, got {:?}", r),
+        }
+    }
+}
@@ -1,8 +1,50 @@
+mod backend;
+#[cfg(test)]
+mod conformance;
+mod damage;
+#[cfg(target_os = "linux")]
+mod fbdev;
+mod headless;
+mod memory;
+mod pixel_perfect;
+mod pixels;
+pub mod platform;
 mod platform_impl;
-use raw_window_handle::HasRawWindowHandle;
+mod presenter;
+mod rect;
+mod scale;
+mod swap_chain;
+mod target;
+mod terminal;
+mod vnc;
+mod window_surface;
+
+#[cfg(target_os = "linux")]
+pub use crate::fbdev::{Framebuffer, FramebufferInfo};
+pub use crate::{
+    headless::HeadlessWindow,
+    pixel_perfect::PixelPerfectSurface,
+    pixels::{PixelView, PixelViewMut, Pixels, PixelsMut},
+    presenter::Presenter,
+    rect::{Rect, SourceBounds},
+    scale::ScaleFilter,
+    swap_chain::SwapChain,
+    target::BlitTarget,
+    terminal::{Terminal, TerminalMode},
+    vnc::VncServer,
+    window_surface::WindowSurface,
+};
+
+use crate::{
+    backend::{slice_layout, Backend},
+    damage::Damage,
+    target::Target,
+};
 use std::{
     borrow::{Borrow, BorrowMut},
-    fmt::Debug,
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt::{self, Debug},
     io,
     marker::PhantomData,
 };
@@ -10,16 +52,90 @@ use std::{
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
 
-#[derive(Debug, Clone)]
-pub enum PixelBufferCreationError {
+/// An error that occurred while creating or blitting a pixel buffer.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BlitError {
+    /// The window doesn't support the requested pixel format.
     FormatNotSupported,
+    /// The window handle is of a type this platform can't draw onto.
+    UnsupportedHandle,
+    /// The pixel buffer was blitted onto a different window than the one it was created for.
+    WindowMismatch,
+    /// A size or position is too large for the platform to handle.
+    DimensionsTooLarge,
+    /// The memory for the pixel buffer couldn't be allocated.
+    AllocationFailed(io::Error),
+    /// The blitted rectangle doesn't lie within the pixel buffer.
+    OutOfBounds,
+    /// A slice of pixels is too small for the given dimensions and stride.
+    SliceTooSmall,
+    /// A scale factor isn't a positive, finite number.
+    InvalidScaleFactor,
+    /// The platform failed to present the pixels.
+    Io(io::Error),
+}
+
+/// The error returned when creating a pixel buffer.
+pub type PixelBufferCreationError = BlitError;
+
+impl fmt::Display for BlitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlitError::FormatNotSupported => write!(f, "pixel format not supported by the window"),
+            BlitError::UnsupportedHandle => write!(f, "unsupported window handle type"),
+            BlitError::WindowMismatch => write!(
+                f,
+                "pixel buffer blitted onto a different window than it was created for"
+            ),
+            BlitError::DimensionsTooLarge => write!(f, "pixel dimensions too large"),
+            BlitError::AllocationFailed(e) => write!(f, "failed to allocate pixel buffer: {}", e),
+            BlitError::OutOfBounds => {
+                write!(f, "blitted rectangle out of the pixel buffer's bounds")
+            }
+            BlitError::SliceTooSmall => {
+                write!(f, "pixel slice too small for its dimensions and stride")
+            }
+            BlitError::InvalidScaleFactor => {
+                write!(f, "scale factor not a positive, finite number")
+            }
+            BlitError::Io(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl Error for BlitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlitError::AllocationFailed(e) | BlitError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BlitError {
+    fn from(e: io::Error) -> BlitError {
+        BlitError::Io(e)
+    }
 }
 
 /// A buffer of pixels that can be blitted onto a window.
 ///
 /// The pixel buffer's origin is in the top-left corner of the image.
 pub struct PixelBuffer {
-    p: platform_impl::PixelBuffer,
+    p: Backend,
+    format: PixelBufferFormatType,
+    /// Used by `blit_from_slice` to present images the platform can't present directly.
+    staging: Option<Backend>,
+    source_bounds: SourceBounds,
+    /// The parts of the buffer changed since the last `blit_damaged`, if damage is tracked.
+    damage: Option<Damage>,
+    /// See `buffer_age`.
+    age: Cell<u32>,
+    alpha: AlphaMode,
+    /// The pixels to present when `alpha` is `Straight`, premultiplied on every blit.
+    premultiplied: RefCell<Option<Backend>>,
+    scale_factor: f64,
 }
 
 /// A buffer of pixels with a statically-checked pixel format.
@@ -30,52 +146,537 @@ pub struct PixelBufferTyped<P: PixelBufferFormat> {
     _format: PhantomData<P>,
 }
 
+/// What happens to a pixel buffer's contents when it gets resized.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResizeContents {
+    /// Pixels within both the old and new size keep their values, and any new pixels are zeroed.
+    #[default]
+    Preserve,
+    /// Every pixel is zeroed.
+    Clear,
+    /// The contents are unspecified, which avoids any copying or clearing. Use this if the whole
+    /// buffer gets redrawn after resizing.
+    Undefined,
+}
+
+/// How a pixel buffer's alpha channel affects the window it gets presented on.
+///
+/// Every platform expects premultiplied alpha, so buffers with straight alpha get premultiplied
+/// into a copy of the buffer on every blit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// The window is opaque. Pixels should still have an opaque alpha channel, since X11 windows
+    /// with an ARGB visual present it regardless.
+    #[default]
+    Opaque,
+    /// The window is translucent, and the color channels are independent of the alpha channel.
+    Straight,
+    /// The window is translucent, and the color channels have already been multiplied by the
+    /// alpha channel.
+    Premultiplied,
+}
+
 impl PixelBufferFormatType {
     /// The native pixel buffer format for the current plaform.
     pub const NATIVE: PixelBufferFormatType = NativeFormat::FORMAT_TYPE;
 }
 
+fn check_scale_factor(scale_factor: f64) -> Result<(), BlitError> {
+    match scale_factor.is_normal() && scale_factor > 0.0 {
+        true => Ok(()),
+        false => Err(BlitError::InvalidScaleFactor),
+    }
+}
+
 impl PixelBuffer {
     /// Initialize a new pixel buffer.
     ///
-    /// Can return `Err` if the platform doesn't support the requested pixel buffer type.
-    pub fn new<H: HasRawWindowHandle>(
+    /// Can return `Err` if the platform doesn't support the requested pixel buffer type, the window
+    /// handle, or the buffer's dimensions, or if the buffer couldn't be allocated. Buffers
+    /// for a [`HeadlessWindow`] must use the same format as the window.
+    pub fn new<H: BlitTarget>(
         width: u32,
         height: u32,
         format: PixelBufferFormatType,
         window: &H,
     ) -> Result<PixelBuffer, PixelBufferCreationError> {
-        unsafe {
-            platform_impl::PixelBuffer::new(width, height, format, window.raw_window_handle())
-                .map(|p| PixelBuffer { p })
-        }
+        Backend::new(width, height, format, window.target()).map(|p| PixelBuffer {
+            p,
+            format,
+            staging: None,
+            source_bounds: SourceBounds::default(),
+            damage: None,
+            age: Cell::new(0),
+            alpha: AlphaMode::default(),
+            premultiplied: RefCell::new(None),
+            scale_factor: 1.0,
+        })
+    }
+
+    /// Initialize a new pixel buffer with a copy of the image in `data`.
+    ///
+    /// The image's rows are `stride` bytes apart, and must be in `format`. Use
+    /// [`blit_from_slice`](Self::blit_from_slice) to present an image without keeping a copy
+    /// around.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::SliceTooSmall`] if `data` doesn't hold the whole image, and otherwise
+    /// the same errors as [`new`](Self::new).
+    pub fn from_slice<H: BlitTarget>(
+        data: &[u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelBufferFormatType,
+        window: &H,
+    ) -> Result<PixelBuffer, PixelBufferCreationError> {
+        let layout = slice_layout(data.len(), width, height, stride, format)?;
+        let mut buffer = PixelBuffer::new(width, height, format, window)?;
+        buffer.p.copy_from_slice(data, layout);
+        Ok(buffer)
+    }
+
+    /// Initialize a new pixel buffer covering `width` by `height` logical pixels, at the given
+    /// scale factor.
+    ///
+    /// The physical size gets rounded to the nearest pixel, the same way winit converts logical
+    /// sizes.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::InvalidScaleFactor`] if `scale_factor` isn't a positive, finite
+    /// number, [`BlitError::DimensionsTooLarge`] if the physical size is negative or too large,
+    /// and otherwise the same errors as [`new`](Self::new).
+    pub fn from_logical_size<H: BlitTarget>(
+        width: f64,
+        height: f64,
+        scale_factor: f64,
+        format: PixelBufferFormatType,
+        window: &H,
+    ) -> Result<PixelBuffer, PixelBufferCreationError> {
+        check_scale_factor(scale_factor)?;
+        let physical = |len: f64| {
+            let len = (len * scale_factor).round();
+            match (0.0..=f64::from(u32::MAX)).contains(&len) {
+                true => Ok(len as u32),
+                false => Err(BlitError::DimensionsTooLarge),
+            }
+        };
+        let (physical_width, physical_height) = (physical(width)?, physical(height)?);
+        let mut buffer = PixelBuffer::new(physical_width, physical_height, format, window)?;
+        buffer.scale_factor = scale_factor;
+        Ok(buffer)
     }
 
     /// Blits the pixel buffer's contents onto `window`.
     ///
-    /// # Panics
+    /// # Errors
     /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
-    /// do so will result in a panic.
-    pub fn blit<H: HasRawWindowHandle>(&self, window: &H) -> io::Result<()> {
-        unsafe { self.p.blit(window.raw_window_handle()) }
+    /// do so returns [`BlitError::WindowMismatch`].
+    pub fn blit<H: BlitTarget>(&self, window: &H) -> Result<(), BlitError> {
+        let target = window.target();
+        self.present(target, |p| p.blit(target))?;
+        self.age.set(1);
+        Ok(())
     }
 
     /// Blits a subsection of the pixel buffer's contents onto `window`.
     ///
-    /// # Panics
+    /// The `blit_size` rectangle at `src_pos` gets clipped to the window's client area. Parts of
+    /// the rectangle outside of the pixel buffer are handled according to
+    /// [`source_bounds`](Self::source_bounds).
+    ///
+    /// # Errors
     /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
-    /// do so will result in a panic.
-    pub fn blit_rect<H: HasRawWindowHandle>(
+    /// do so returns [`BlitError::WindowMismatch`].
+    pub fn blit_rect<H: BlitTarget>(
         &self,
         src_pos: (u32, u32),
         dst_pos: (u32, u32),
         blit_size: (u32, u32),
         window: &H,
-    ) -> io::Result<()> {
-        unsafe {
-            self.p
-                .blit_rect(src_pos, dst_pos, blit_size, window.raw_window_handle())
+    ) -> Result<(), BlitError> {
+        // Positions this large are outside of every buffer and window anyway.
+        let clamp = |n: u32| n.min(i32::MAX as u32) as i32;
+        let src = Rect::new(clamp(src_pos.0), clamp(src_pos.1), blit_size.0, blit_size.1);
+        self.blit_rect_at(src, (clamp(dst_pos.0), clamp(dst_pos.1)), window)
+    }
+
+    /// Blits the `src` rectangle of the pixel buffer's contents onto `window`, with its top-left
+    /// corner at `dst_pos`.
+    ///
+    /// Unlike [`blit_rect`](Self::blit_rect), the positions may be negative, which makes it easy
+    /// to draw sprites that are partially off-screen. The rectangle gets clipped to the window's
+    /// client area, and parts of it outside of the pixel buffer are handled according to
+    /// [`source_bounds`](Self::source_bounds).
+    ///
+    /// # Errors
+    /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
+    /// do so returns [`BlitError::WindowMismatch`].
+    pub fn blit_rect_at<H: BlitTarget>(
+        &self,
+        src: Rect,
+        dst_pos: (i32, i32),
+        window: &H,
+    ) -> Result<(), BlitError> {
+        let target = window.target();
+        self.present(target, |p| {
+            p.blit_rect(src, dst_pos, self.source_bounds, target)
+        })?;
+        self.age.set(1);
+        Ok(())
+    }
+
+    /// Blits the `src` rectangle of the pixel buffer's contents onto `window`, scaled to fill
+    /// `dst`.
+    ///
+    /// `dst` gets clipped to the window's client area, and may have a negative position. Windows
+    /// scales with `StretchBlt`, using halftoning for [`ScaleFilter::Bilinear`], unless the buffer
+    /// is translucent. X11 scales with the RENDER extension if the server supports it. Wayland
+    /// scales through `wp_viewporter` if the compositor supports it, but only for
+    /// [`ScaleFilter::Bilinear`] blits whose `dst` covers the whole window, since compositors
+    /// pick their own filtering. Otherwise, the pixels get scaled into a staging buffer that's
+    /// kept around for the next call. Wayland surfaces take on the size of the pixel buffer, so
+    /// there the rest of the surface keeps showing what was presented before.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::OutOfBounds`] if `src` doesn't lie within the pixel buffer, and
+    /// otherwise the same errors as [`blit`](Self::blit).
+    pub fn blit_scaled<H: BlitTarget>(
+        &mut self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        window: &H,
+    ) -> Result<(), BlitError> {
+        if !Rect::from_size(self.width(), self.height()).contains_rect(&src) {
+            return Err(BlitError::OutOfBounds);
         }
+        let target = window.target();
+        let mut staging = self.staging.take();
+        let result = self.present(target, |p| {
+            let (width, height) = p.window_size(target)?;
+            if src.is_empty() || dst.is_empty() || p.blit_scaled(src, dst, filter, target)? {
+                return Ok(());
+            }
+            let window = Rect::from_size(width.min(i32::MAX as u32), height.min(i32::MAX as u32));
+            let visible = match dst.intersection(&window) {
+                Some(visible) => visible,
+                None => return Ok(()),
+            };
+            let staging = match &mut staging {
+                Some(staging) => {
+                    staging.resize(visible.width, visible.height, ResizeContents::Undefined)?;
+                    staging
+                }
+                staging => {
+                    staging.insert(self.new_staging(visible.width, visible.height, target)?)
+                }
+            };
+            scale::scale(p, src, staging, dst, visible, filter);
+            p.blit_staging(staging, (visible.x, visible.y), target)
+        });
+        self.staging = staging;
+        result?;
+        self.age.set(1);
+        Ok(())
+    }
+
+    /// Blits the image in `data` onto the top-left corner of `window`, without going through the
+    /// pixel buffer's contents.
+    ///
+    /// The image's rows are `stride` bytes apart, and must be in the pixel buffer's format. X11,
+    /// and Windows if `stride` is a multiple of four, present the image straight out of `data`.
+    /// Wayland copies it straight into the surface's memory. Otherwise, it gets copied into a
+    /// staging buffer that's kept around for the next call. Either way, the pixel buffer's own
+    /// contents are left alone, but the window no longer shows them: the
+    /// [`buffer_age`](Self::buffer_age) goes back to `0`, and the whole buffer counts as damaged.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::SliceTooSmall`] if `data` doesn't hold the whole image,
+    /// [`BlitError::FormatNotSupported`] if `format` isn't the pixel buffer's format, and
+    /// otherwise the same errors as [`blit`](Self::blit).
+    pub fn blit_from_slice<H: BlitTarget>(
+        &mut self,
+        window: &H,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        stride: usize,
+        format: PixelBufferFormatType,
+    ) -> Result<(), BlitError> {
+        let layout = slice_layout(data.len(), width, height, stride, format)?;
+        if format != self.format {
+            return Err(BlitError::FormatNotSupported);
+        }
+        let straight = self.alpha == AlphaMode::Straight;
+        if straight {
+            // The pixels need premultiplying first, so they can't be presented directly.
+            self.p.check_target(window.target())?;
+        } else if self.p.blit_slice(data, layout, window.target())? {
+            self.invalidate();
+            return Ok(());
+        }
+
+        let staging = match self.staging.take() {
+            Some(mut staging) => {
+                staging.resize(width, height, ResizeContents::Undefined)?;
+                staging
+            }
+            None => self.new_staging(width, height, window.target())?,
+        };
+        let staging = self.staging.insert(staging);
+        staging.copy_from_slice(data, layout);
+        if straight {
+            staging.premultiply();
+        }
+        self.p.blit_staging(staging, (0, 0), window.target())?;
+        self.invalidate();
+        Ok(())
+    }
+
+    /// How blits treat source rectangles that extend past the edges of the pixel buffer.
+    ///
+    /// Defaults to [`SourceBounds::Clip`].
+    pub fn source_bounds(&self) -> SourceBounds {
+        self.source_bounds
+    }
+
+    /// Sets how blits treat source rectangles that extend past the edges of the pixel buffer.
+    pub fn set_source_bounds(&mut self, source_bounds: SourceBounds) {
+        self.source_bounds = source_bounds;
+    }
+
+    /// How the buffer's alpha channel affects the window.
+    ///
+    /// Defaults to [`AlphaMode::Opaque`].
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.alpha
+    }
+
+    /// Sets how the buffer's alpha channel affects the window.
+    ///
+    /// Translucent windows are presented through layered windows on Windows, and `ARGB8888`
+    /// buffers on Wayland. Windows presents the whole buffer on every blit, and resizes the
+    /// window to the buffer's size. X11 only supports translucency if the window was created
+    /// with a 32-bit ARGB visual.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::FormatNotSupported`] if the buffer's format has no alpha channel, or
+    /// the window can't be made translucent. The alpha mode is left unchanged in that case.
+    pub fn set_alpha_mode(&mut self, alpha: AlphaMode) -> Result<(), BlitError> {
+        let translucent = alpha != AlphaMode::Opaque;
+        let has_alpha = matches!(
+            self.format,
+            PixelBufferFormatType::BGRA | PixelBufferFormatType::RGBA
+        );
+        if translucent && !has_alpha {
+            return Err(BlitError::FormatNotSupported);
+        }
+        self.p.set_alpha(translucent)?;
+        self.alpha = alpha;
+        self.staging = None;
+        *self.premultiplied.get_mut() = None;
+        Ok(())
+    }
+
+    /// Creates a buffer for `target` with the same format and alpha mode as this one.
+    fn new_staging(
+        &self,
+        width: u32,
+        height: u32,
+        target: Target<'_>,
+    ) -> Result<Backend, BlitError> {
+        let mut staging = Backend::new(width, height, self.format, target)?;
+        staging.set_alpha(self.alpha != AlphaMode::Opaque)?;
+        Ok(staging)
+    }
+
+    /// Presents the buffer's pixels onto `target` through `present`, premultiplying them into a
+    /// copy first if the buffer has straight alpha.
+    fn present(
+        &self,
+        target: Target<'_>,
+        present: impl FnOnce(&Backend) -> Result<(), BlitError>,
+    ) -> Result<(), BlitError> {
+        if self.alpha != AlphaMode::Straight {
+            return present(&self.p);
+        }
+        // The copy gets created for whichever target comes first, so make sure it's the right one.
+        self.p.check_target(target)?;
+        let mut premultiplied = self.premultiplied.borrow_mut();
+        let premultiplied = match &mut *premultiplied {
+            Some(premultiplied) => premultiplied,
+            premultiplied => {
+                premultiplied.insert(self.new_staging(self.width(), self.height(), target)?)
+            }
+        };
+        premultiplied.premultiply_from(&self.p)?;
+        present(premultiplied)
+    }
+
+    /// Changes the size of the pixel buffer, treating its existing contents according to
+    /// `contents`.
+    ///
+    /// The buffer's memory gets reused if it's large enough, so shrinking a buffer and growing it
+    /// back is cheap. Otherwise the buffer gets reallocated, large enough to hold both the old and
+    /// the new size. [`row_len`](Self::row_len) may change either way.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::DimensionsTooLarge`] if the platform doesn't support the new size,
+    /// or [`BlitError::AllocationFailed`] if the memory couldn't be reallocated. The buffer is
+    /// left unchanged in both cases.
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+        contents: ResizeContents,
+    ) -> Result<(), BlitError> {
+        self.p.resize(width, height, contents)?;
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Marks the window as no longer showing the buffer's contents.
+    fn invalidate(&mut self) {
+        self.age.set(0);
+        let bounds = Rect::from_size(self.width(), self.height());
+        if let Some(damage) = &mut self.damage {
+            damage.clear();
+            damage.add(bounds);
+        }
+    }
+
+    /// Whether the buffer records which of its parts change. See
+    /// [`set_damage_tracking`](Self::set_damage_tracking).
+    pub fn damage_tracking(&self) -> bool {
+        self.damage.is_some()
+    }
+
+    /// Enables or disables damage tracking.
+    ///
+    /// While enabled, the buffer records which rectangles get changed through
+    /// [`row_mut`](Self::row_mut), [`rows_mut`](Self::rows_mut),
+    /// [`region_rows_mut`](Self::region_rows_mut) and [`add_damage`](Self::add_damage), and
+    /// [`blit_damaged`](Self::blit_damaged) only presents those. Enabling damage tracking marks
+    /// the whole buffer as damaged.
+    pub fn set_damage_tracking(&mut self, enabled: bool) {
+        self.damage = match enabled {
+            true => {
+                let mut damage = Damage::default();
+                damage.add(Rect::from_size(self.width(), self.height()));
+                Some(damage)
+            }
+            false => None,
+        };
+    }
+
+    /// The rectangles changed since the last [`blit_damaged`](Self::blit_damaged). Overlapping
+    /// and nearby rectangles get merged, so they may cover some unchanged pixels too.
+    ///
+    /// Always empty if damage tracking is disabled.
+    pub fn damage(&self) -> &[Rect] {
+        match &self.damage {
+            Some(damage) => damage.rects(),
+            None => &[],
+        }
+    }
+
+    /// Marks `rect` as damaged, for changes made without going through the buffer's methods.
+    /// Does nothing if damage tracking is disabled.
+    pub fn add_damage(&mut self, rect: Rect) {
+        let bounds = Rect::from_size(self.width(), self.height());
+        if let (Some(damage), Some(rect)) = (&mut self.damage, rect.intersection(&bounds)) {
+            damage.add(rect);
+        }
+    }
+
+    /// Blits the parts of the buffer that changed since the last call onto `window`, then clears
+    /// the damage. Blits the whole buffer if damage tracking is disabled.
+    ///
+    /// Wayland presents all the changed rectangles at once. Other platforms blit each of them in
+    /// turn.
+    ///
+    /// # Errors
+    /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
+    /// do so returns [`BlitError::WindowMismatch`]. The damage is kept if the blit fails.
+    pub fn blit_damaged<H: BlitTarget>(&mut self, window: &H) -> Result<(), BlitError> {
+        match &self.damage {
+            Some(damage) => self.blit_rects(damage.rects(), window)?,
+            None => self.blit(window)?,
+        }
+        if let Some(damage) = &mut self.damage {
+            damage.clear();
+        }
+        Ok(())
+    }
+
+    /// Blits each of `rects` onto the same position in `window`, all at once where the platform
+    /// allows it.
+    pub(crate) fn blit_rects<H: BlitTarget>(
+        &self,
+        rects: &[Rect],
+        window: &H,
+    ) -> Result<(), BlitError> {
+        let target = window.target();
+        self.present(target, |p| p.blit_rects(rects, target))?;
+        self.age.set(1);
+        Ok(())
+    }
+
+    /// How many presents ago the buffer's contents were presented, following the semantics of
+    /// `EGL_EXT_buffer_age`.
+    ///
+    /// `0` means the contents are undefined as far as the window is concerned, which is the case
+    /// until the buffer gets blitted for the first time, after it gets resized, and after
+    /// [`blit_from_slice`](PixelBuffer::blit_from_slice) presents another image. Every backend
+    /// keeps the buffer's contents intact when blitting, so the age is `1` after any successful
+    /// blit: the buffer still holds the frame it just presented, and only the parts that change
+    /// need to be redrawn.
+    ///
+    /// A single pixel buffer can't know about blits made by other buffers onto the same window.
+    /// [`SwapChain::buffer_age`] accounts for those.
+    pub fn buffer_age(&self) -> u32 {
+        self.age.get()
+    }
+
+    /// The ratio between physical pixels, which the buffer is made of, and logical pixels, which
+    /// stay the same size on screen regardless of the display's density.
+    ///
+    /// Defaults to `1.0`. The buffer itself is always sized and addressed in physical pixels; the
+    /// scale factor only affects the conversions between the two.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Sets the scale factor, such as the one winit reports for the window.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::InvalidScaleFactor`] if `scale_factor` isn't a positive, finite
+    /// number, in which case the scale factor is left unchanged.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) -> Result<(), BlitError> {
+        check_scale_factor(scale_factor)?;
+        self.scale_factor = scale_factor;
+        Ok(())
+    }
+
+    /// The size of the buffer in logical pixels.
+    pub fn logical_size(&self) -> (f64, f64) {
+        self.to_logical(f64::from(self.width()), f64::from(self.height()))
+    }
+
+    /// Converts a position or size in logical pixels to physical pixels.
+    pub fn to_physical(&self, x: f64, y: f64) -> (f64, f64) {
+        (x * self.scale_factor, y * self.scale_factor)
+    }
+
+    /// Converts a position or size in physical pixels to logical pixels.
+    pub fn to_logical(&self, x: f64, y: f64) -> (f64, f64) {
+        (x / self.scale_factor, y / self.scale_factor)
+    }
+
+    /// The format of the pixel buffer's pixels.
+    pub fn format(&self) -> PixelBufferFormatType {
+        self.format
     }
 
     /// The total number of bits in an individual pixel.
@@ -112,30 +713,81 @@ impl PixelBuffer {
 
     /// Mutably gets the row at the particular height.
     pub fn row_mut(&mut self, row: u32) -> Option<&mut [u8]> {
+        self.add_damage(Rect::new(
+            0,
+            row.min(i32::MAX as u32) as i32,
+            self.width(),
+            1,
+        ));
         self.p.row_mut(row)
     }
 
     /// Iterate through all rows in the pixel buffer.
-    pub fn rows<'a>(&'a self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a [u8]> {
+    pub fn rows(&self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &[u8]> {
         self.p.rows()
     }
 
     /// Mutably iterate through all rows in the pixel buffer.
-    pub fn rows_mut<'a>(
-        &'a mut self,
-    ) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a mut [u8]> {
+    ///
+    /// Marks the whole buffer as damaged. Use [`region_rows_mut`](Self::region_rows_mut) to
+    /// change only part of it.
+    pub fn rows_mut(&mut self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &mut [u8]> {
+        self.add_damage(Rect::from_size(self.width(), self.height()));
         self.p.rows_mut()
     }
 
+    /// The whole pixel buffer as a single slice of bytes.
+    ///
+    /// Rows are stored from top to bottom, each starting [`row_len`](Self::row_len) bytes after
+    /// the previous one. Any bytes past the end of a row's pixels are padding.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.p.bytes()
+    }
+
+    /// The whole pixel buffer as a single mutable slice of bytes, laid out like
+    /// [`as_bytes`](Self::as_bytes).
+    ///
+    /// Marks the whole buffer as damaged.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.add_damage(Rect::from_size(self.width(), self.height()));
+        self.p.bytes_mut()
+    }
+
+    /// Mutably iterate through the part of each row that lies within `rect`.
+    ///
+    /// The rectangle gets clipped to the pixel buffer, and marked as damaged.
+    pub fn region_rows_mut(
+        &mut self,
+        rect: Rect,
+    ) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &mut [u8]> {
+        let rect = rect
+            .intersection(&Rect::from_size(self.width(), self.height()))
+            .unwrap_or_default();
+        self.add_damage(rect);
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let (start, end) = (
+            rect.x as usize * bytes_per_pixel,
+            rect.right() as usize * bytes_per_pixel,
+        );
+        self.p
+            .rows_mut()
+            .skip(rect.y as usize)
+            .take(rect.height as usize)
+            .map(move |row| &mut row[start..end])
+    }
+
     /// Iterate through all rows in the pixel buffer.
     #[cfg(feature = "rayon")]
-    pub fn par_rows<'a>(&'a self) -> impl IndexedParallelIterator<Item = &'a [u8]> {
+    pub fn par_rows(&self) -> impl IndexedParallelIterator<Item = &[u8]> {
         self.p.par_rows()
     }
 
     /// Mutably iterate through all rows in the pixel buffer.
+    ///
+    /// Marks the whole buffer as damaged.
     #[cfg(feature = "rayon")]
-    pub fn par_rows_mut<'a>(&'a mut self) -> impl IndexedParallelIterator<Item = &'a mut [u8]> {
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = &mut [u8]> {
+        self.add_damage(Rect::from_size(self.width(), self.height()));
         self.p.par_rows_mut()
     }
 }
@@ -144,7 +796,7 @@ impl<P: PixelBufferFormat> PixelBufferTyped<P> {
     /// Initialize a new pixel buffer.
     ///
     /// Can return `Err` if the platform doesn't support the requested pixel buffer type.
-    pub fn new<H: HasRawWindowHandle>(
+    pub fn new<H: BlitTarget>(
         width: u32,
         height: u32,
         window: &H,
@@ -157,43 +809,290 @@ impl<P: PixelBufferFormat> PixelBufferTyped<P> {
 
     /// Initialize a new pixel buffer.
     ///
-    /// This always works, since we've statically checked that the pixel format is supported by
-    /// the platform.
-    pub fn new_supported<H: HasRawWindowHandle>(
-        width: u32,
-        height: u32,
-        window: &H,
-    ) -> PixelBufferTyped<P>
+    /// The pixel format is statically checked to be supported by the platform.
+    ///
+    /// # Panics
+    /// Panics if the buffer can't be created for any other reason, such as the allocation failing.
+    pub fn new_supported<H: BlitTarget>(width: u32, height: u32, window: &H) -> PixelBufferTyped<P>
     where
         P: PixelBufferFormatSupported,
     {
         Self::new(width, height, window).unwrap()
     }
 
+    /// Initialize a new pixel buffer with a copy of the image in `data`.
+    ///
+    /// The image's rows are `stride` pixels apart. Use
+    /// [`blit_from_slice`](Self::blit_from_slice) to present an image without keeping a copy
+    /// around.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::SliceTooSmall`] if `data` doesn't hold the whole image, and otherwise
+    /// the same errors as [`new`](Self::new).
+    pub fn from_slice<H: BlitTarget>(
+        data: &[P],
+        width: u32,
+        height: u32,
+        stride: usize,
+        window: &H,
+    ) -> Result<PixelBufferTyped<P>, PixelBufferCreationError> {
+        Ok(PixelBufferTyped {
+            p: PixelBuffer::from_slice(
+                P::to_raw_slice(data),
+                width,
+                height,
+                stride.saturating_mul(std::mem::size_of::<P>()),
+                P::FORMAT_TYPE,
+                window,
+            )?,
+            _format: PhantomData,
+        })
+    }
+
+    /// Initialize a new pixel buffer covering `width` by `height` logical pixels, at the given
+    /// scale factor. See [`PixelBuffer::from_logical_size`].
+    pub fn from_logical_size<H: BlitTarget>(
+        width: f64,
+        height: f64,
+        scale_factor: f64,
+        window: &H,
+    ) -> Result<PixelBufferTyped<P>, PixelBufferCreationError> {
+        Ok(PixelBufferTyped {
+            p: PixelBuffer::from_logical_size(width, height, scale_factor, P::FORMAT_TYPE, window)?,
+            _format: PhantomData,
+        })
+    }
+
     /// Blits the pixel buffer's contents onto `window`.
     ///
-    /// # Panics
+    /// # Errors
     /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
-    /// do so will result in a panic.
-    pub fn blit<H: HasRawWindowHandle>(&self, window: &H) -> io::Result<()> {
+    /// do so returns [`BlitError::WindowMismatch`].
+    pub fn blit<H: BlitTarget>(&self, window: &H) -> Result<(), BlitError> {
         self.p.blit(window)
     }
 
     /// Blits a subsection of the pixel buffer's contents onto `window`.
     ///
-    /// # Panics
+    /// The `blit_size` rectangle at `src_pos` gets clipped to the window's client area. Parts of
+    /// the rectangle outside of the pixel buffer are handled according to
+    /// [`source_bounds`](Self::source_bounds).
+    ///
+    /// # Errors
     /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
-    /// do so will result in a panic.
-    pub fn blit_rect<H: HasRawWindowHandle>(
+    /// do so returns [`BlitError::WindowMismatch`].
+    pub fn blit_rect<H: BlitTarget>(
         &self,
         src_pos: (u32, u32),
         dst_pos: (u32, u32),
         blit_size: (u32, u32),
         window: &H,
-    ) -> io::Result<()> {
+    ) -> Result<(), BlitError> {
         self.p.blit_rect(src_pos, dst_pos, blit_size, window)
     }
 
+    /// Blits the `src` rectangle of the pixel buffer's contents onto `window`, with its top-left
+    /// corner at `dst_pos`.
+    ///
+    /// Unlike [`blit_rect`](Self::blit_rect), the positions may be negative, which makes it easy
+    /// to draw sprites that are partially off-screen. The rectangle gets clipped to the window's
+    /// client area, and parts of it outside of the pixel buffer are handled according to
+    /// [`source_bounds`](Self::source_bounds).
+    ///
+    /// # Errors
+    /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
+    /// do so returns [`BlitError::WindowMismatch`].
+    pub fn blit_rect_at<H: BlitTarget>(
+        &self,
+        src: Rect,
+        dst_pos: (i32, i32),
+        window: &H,
+    ) -> Result<(), BlitError> {
+        self.p.blit_rect_at(src, dst_pos, window)
+    }
+
+    /// Blits the `src` rectangle of the pixel buffer's contents onto `window`, scaled to fill
+    /// `dst`. See [`PixelBuffer::blit_scaled`].
+    pub fn blit_scaled<H: BlitTarget>(
+        &mut self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        window: &H,
+    ) -> Result<(), BlitError> {
+        self.p.blit_scaled(src, dst, filter, window)
+    }
+
+    /// Blits the image in `data` onto the top-left corner of `window`, without going through the
+    /// pixel buffer's contents.
+    ///
+    /// The image's rows are `stride` pixels apart. X11, and Windows if the rows are a multiple of
+    /// four bytes apart, present the image straight out of `data`. Otherwise, it gets copied into
+    /// a staging buffer that's kept around for the next call. Either way, the pixel buffer's own
+    /// contents are left alone, but the window no longer shows them. See
+    /// [`PixelBuffer::blit_from_slice`].
+    ///
+    /// # Errors
+    /// Returns [`BlitError::SliceTooSmall`] if `data` doesn't hold the whole image, and otherwise
+    /// the same errors as [`blit`](Self::blit).
+    pub fn blit_from_slice<H: BlitTarget>(
+        &mut self,
+        window: &H,
+        data: &[P],
+        width: u32,
+        height: u32,
+        stride: usize,
+    ) -> Result<(), BlitError> {
+        self.p.blit_from_slice(
+            window,
+            P::to_raw_slice(data),
+            width,
+            height,
+            stride.saturating_mul(std::mem::size_of::<P>()),
+            P::FORMAT_TYPE,
+        )
+    }
+
+    /// How blits treat source rectangles that extend past the edges of the pixel buffer.
+    ///
+    /// Defaults to [`SourceBounds::Clip`].
+    pub fn source_bounds(&self) -> SourceBounds {
+        self.p.source_bounds()
+    }
+
+    /// Sets how blits treat source rectangles that extend past the edges of the pixel buffer.
+    pub fn set_source_bounds(&mut self, source_bounds: SourceBounds) {
+        self.p.set_source_bounds(source_bounds)
+    }
+
+    /// How the buffer's alpha channel affects the window.
+    ///
+    /// Defaults to [`AlphaMode::Opaque`].
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.p.alpha_mode()
+    }
+
+    /// Sets how the buffer's alpha channel affects the window. See
+    /// [`PixelBuffer::set_alpha_mode`].
+    pub fn set_alpha_mode(&mut self, alpha: AlphaMode) -> Result<(), BlitError> {
+        self.p.set_alpha_mode(alpha)
+    }
+
+    /// Changes the size of the pixel buffer, treating its existing contents according to
+    /// `contents`.
+    ///
+    /// The buffer's memory gets reused if it's large enough, so shrinking a buffer and growing it
+    /// back is cheap. Otherwise the buffer gets reallocated, large enough to hold both the old and
+    /// the new size.
+    ///
+    /// # Errors
+    /// Returns [`BlitError::DimensionsTooLarge`] if the platform doesn't support the new size,
+    /// or [`BlitError::AllocationFailed`] if the memory couldn't be reallocated. The buffer is
+    /// left unchanged in both cases.
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+        contents: ResizeContents,
+    ) -> Result<(), BlitError> {
+        self.p.resize(width, height, contents)
+    }
+
+    /// Whether the buffer records which of its parts change. See
+    /// [`set_damage_tracking`](Self::set_damage_tracking).
+    pub fn damage_tracking(&self) -> bool {
+        self.p.damage_tracking()
+    }
+
+    /// Enables or disables damage tracking.
+    ///
+    /// While enabled, the buffer records which rectangles get changed through
+    /// [`row_mut`](Self::row_mut), [`rows_mut`](Self::rows_mut),
+    /// [`region_rows_mut`](Self::region_rows_mut) and [`add_damage`](Self::add_damage), and
+    /// [`blit_damaged`](Self::blit_damaged) only presents those. Enabling damage tracking marks
+    /// the whole buffer as damaged.
+    pub fn set_damage_tracking(&mut self, enabled: bool) {
+        self.p.set_damage_tracking(enabled)
+    }
+
+    /// The rectangles changed since the last [`blit_damaged`](Self::blit_damaged). Overlapping
+    /// and nearby rectangles get merged, so they may cover some unchanged pixels too.
+    ///
+    /// Always empty if damage tracking is disabled.
+    pub fn damage(&self) -> &[Rect] {
+        self.p.damage()
+    }
+
+    /// Marks `rect` as damaged, for changes made without going through the buffer's methods.
+    /// Does nothing if damage tracking is disabled.
+    pub fn add_damage(&mut self, rect: Rect) {
+        self.p.add_damage(rect)
+    }
+
+    /// Blits the parts of the buffer that changed since the last call onto `window`, then clears
+    /// the damage. Blits the whole buffer if damage tracking is disabled.
+    ///
+    /// Wayland presents all the changed rectangles at once. Other platforms blit each of them in
+    /// turn.
+    ///
+    /// # Errors
+    /// The `window` passed to this function must be the same `window` passed to `new`. Failing to
+    /// do so returns [`BlitError::WindowMismatch`]. The damage is kept if the blit fails.
+    pub fn blit_damaged<H: BlitTarget>(&mut self, window: &H) -> Result<(), BlitError> {
+        self.p.blit_damaged(window)
+    }
+
+    pub(crate) fn blit_rects<H: BlitTarget>(
+        &self,
+        rects: &[Rect],
+        window: &H,
+    ) -> Result<(), BlitError> {
+        self.p.blit_rects(rects, window)
+    }
+
+    /// How many presents ago the buffer's contents were presented, following the semantics of
+    /// `EGL_EXT_buffer_age`.
+    ///
+    /// `0` means the contents are undefined as far as the window is concerned, which is the case
+    /// until the buffer gets blitted for the first time, after it gets resized, and after
+    /// [`blit_from_slice`](PixelBuffer::blit_from_slice) presents another image. Every backend
+    /// keeps the buffer's contents intact when blitting, so the age is `1` after any successful
+    /// blit: the buffer still holds the frame it just presented, and only the parts that change
+    /// need to be redrawn.
+    ///
+    /// A single pixel buffer can't know about blits made by other buffers onto the same window.
+    /// [`SwapChain::buffer_age`] accounts for those.
+    pub fn buffer_age(&self) -> u32 {
+        self.p.buffer_age()
+    }
+
+    /// The ratio between physical pixels, which the buffer is made of, and logical pixels. See
+    /// [`PixelBuffer::scale_factor`].
+    pub fn scale_factor(&self) -> f64 {
+        self.p.scale_factor()
+    }
+
+    /// Sets the scale factor, such as the one winit reports for the window. See
+    /// [`PixelBuffer::set_scale_factor`].
+    pub fn set_scale_factor(&mut self, scale_factor: f64) -> Result<(), BlitError> {
+        self.p.set_scale_factor(scale_factor)
+    }
+
+    /// The size of the buffer in logical pixels.
+    pub fn logical_size(&self) -> (f64, f64) {
+        self.p.logical_size()
+    }
+
+    /// Converts a position or size in logical pixels to physical pixels.
+    pub fn to_physical(&self, x: f64, y: f64) -> (f64, f64) {
+        self.p.to_physical(x, y)
+    }
+
+    /// Converts a position or size in physical pixels to logical pixels.
+    pub fn to_logical(&self, x: f64, y: f64) -> (f64, f64) {
+        self.p.to_logical(x, y)
+    }
+
     /// The total number of bits in an individual pixel.
     ///
     /// Will always be a multiple of `8`.
@@ -232,20 +1131,77 @@ impl<P: PixelBufferFormat> PixelBufferTyped<P> {
     }
 
     /// Iterate through all rows in the pixel buffer.
-    pub fn rows<'a>(&'a self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a [P]> {
+    pub fn rows(&self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &[P]> {
         self.p.rows().map(P::from_raw_slice)
     }
 
     /// Mutably iterate through all rows in the pixel buffer.
-    pub fn rows_mut<'a>(
-        &'a mut self,
-    ) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a mut [P]> {
+    ///
+    /// Marks the whole buffer as damaged. Use [`region_rows_mut`](Self::region_rows_mut) to
+    /// change only part of it.
+    pub fn rows_mut(&mut self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &mut [P]> {
         self.p.rows_mut().map(P::from_raw_slice_mut)
     }
 
+    /// The whole pixel buffer as a single slice of bytes.
+    ///
+    /// Rows are stored from top to bottom, each starting [`row_len`](Self::row_len) bytes after
+    /// the previous one. Any bytes past the end of a row's pixels are padding.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.p.as_bytes()
+    }
+
+    /// The whole pixel buffer as a single mutable slice of bytes, laid out like
+    /// [`as_bytes`](Self::as_bytes).
+    ///
+    /// Marks the whole buffer as damaged.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        self.p.as_bytes_mut()
+    }
+
+    /// A 2D view over every pixel in the pixel buffer.
+    pub fn pixels(&self) -> Pixels<'_, P> {
+        let (width, height, row_len) = (self.width(), self.height(), self.row_len());
+        Pixels::new(self.as_bytes(), width, height, row_len)
+    }
+
+    /// A mutable 2D view over every pixel in the pixel buffer.
+    ///
+    /// Marks the whole buffer as damaged.
+    pub fn pixels_mut(&mut self) -> PixelsMut<'_, P> {
+        let (width, height, row_len) = (self.width(), self.height(), self.row_len());
+        PixelsMut::new(self.as_bytes_mut(), width, height, row_len)
+    }
+
+    /// A view of the pixels within `rect`, which gets clipped to the pixel buffer.
+    pub fn view(&self, rect: Rect) -> PixelView<'_, P> {
+        let (width, height, row_len) = (self.width(), self.height(), self.row_len());
+        PixelView::new(self.p.p.bytes(), width, height, row_len, rect)
+    }
+
+    /// A mutable view of the pixels within `rect`, which gets clipped to the pixel buffer.
+    ///
+    /// Marks the rectangle as damaged. The view can be split up to draw into different parts of
+    /// it in parallel.
+    pub fn view_mut(&mut self, rect: Rect) -> PixelViewMut<'_, P> {
+        self.add_damage(rect);
+        let (width, height, row_len) = (self.width(), self.height(), self.row_len());
+        PixelViewMut::new(self.p.p.bytes_mut(), width, height, row_len, rect)
+    }
+
+    /// Mutably iterate through the part of each row that lies within `rect`.
+    ///
+    /// The rectangle gets clipped to the pixel buffer, and marked as damaged.
+    pub fn region_rows_mut(
+        &mut self,
+        rect: Rect,
+    ) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &mut [P]> {
+        self.p.region_rows_mut(rect).map(P::from_raw_slice_mut)
+    }
+
     /// Iterate through all rows in the pixel buffer.
     #[cfg(feature = "rayon")]
-    pub fn par_rows<'a>(&'a self) -> impl IndexedParallelIterator<Item = &'a [P]>
+    pub fn par_rows(&self) -> impl IndexedParallelIterator<Item = &[P]>
     where
         P: Send + Sync,
     {
@@ -253,13 +1209,38 @@ impl<P: PixelBufferFormat> PixelBufferTyped<P> {
     }
 
     /// Mutably iterate through all rows in the pixel buffer.
+    ///
+    /// Marks the whole buffer as damaged.
     #[cfg(feature = "rayon")]
-    pub fn par_rows_mut<'a>(&'a mut self) -> impl IndexedParallelIterator<Item = &'a mut [P]>
+    pub fn par_rows_mut(&mut self) -> impl IndexedParallelIterator<Item = &mut [P]>
     where
         P: Send + Sync,
     {
         self.p.par_rows_mut().map(P::from_raw_slice_mut)
     }
+
+    /// Mutably iterate through tiles of `tile_width` by `tile_height` pixels in parallel, along
+    /// with the position of each tile's top-left corner.
+    ///
+    /// Tiles on the right and bottom edges are smaller if the buffer's size isn't a multiple of
+    /// the tile size. Marks the whole buffer as damaged.
+    ///
+    /// # Panics
+    /// Panics if `tile_width` or `tile_height` is zero.
+    #[cfg(feature = "rayon")]
+    pub fn par_tiles_mut(
+        &mut self,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> impl IndexedParallelIterator<Item = ((u32, u32), PixelViewMut<'_, P>)>
+    where
+        P: Send + Sync,
+    {
+        let rect = Rect::from_size(self.width(), self.height());
+        self.view_mut(rect)
+            .collect_tiles(tile_width, tile_height)
+            .into_par_iter()
+    }
 }
 
 /// The pixel buffer's format. Each variant corresponds to one of the pixel format types.
@@ -282,6 +1263,11 @@ pub enum PixelBufferFormatType {
 /// |         | [`BGR`] | [`BGRA`] | [`RGB`] | [`RGBA`] |
 /// | ------- | ------- | -------- | ------- | -------- |
 /// | Windows | ✔      | ✔      | ❌      | ❌      |
+/// | Unix    | ❌      | ✔      | ❌      | ❌      |
+///
+/// Other formats may still be available at runtime, depending on the display server. On X11, any
+/// format matching the window's visual works, which usually includes [`BGR`]. On Wayland, any
+/// format the compositor advertises through `wl_shm` works.
 pub trait PixelBufferFormatSupported: PixelBufferFormat {}
 /// The format of each individual pixel in the pixel buffer.
 ///
@@ -340,6 +1326,7 @@ macro_rules! pixel_buffer_format {
                     $($c),+
                 }
             }
+            #[allow(clippy::needless_update)]
             pub const fn from_rgb(r: u8, g: u8, b: u8) -> Self {
                 Self {
                     r, g, b,
@@ -499,3 +1486,216 @@ pixel_buffer_format! {
     /// A red-green-blue-alpha formatted pixel type.
     pub struct RGBA(r, g, b, a): [u8; 4] = Self::new(0, 0, 0, 255);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixelbuffer_blit_rect_source_bounds() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((3, 3), (2, 2));
+        pb.row_mut(1).unwrap()[1] = BGR::from_rgb(9, 9, 9);
+
+        pb.set_source_bounds(SourceBounds::Reject);
+        match pb.blit_rect((1, 1), (0, 0), (2, 2), &window) {
+            Err(BlitError::OutOfBounds) => (),
+            r => panic!("expected OutOfBounds, got {:?}", r),
+        }
+        assert_eq!(vec![0; 3], window.first_bytes(0));
+
+        pb.set_source_bounds(SourceBounds::Clip);
+        pb.blit_rect((1, 1), (0, 0), (2, 2), &window).unwrap();
+        assert_eq!(vec![9, 0, 0], window.first_bytes(0));
+        assert_eq!(vec![0; 3], window.first_bytes(1));
+    }
+
+    #[test]
+    fn pixelbuffer_blit_rect_at_negative() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((3, 3), (2, 2));
+        pb.row_mut(1).unwrap()[1] = BGR::from_rgb(9, 9, 9);
+
+        pb.blit_rect_at(Rect::from_size(2, 2), (-1, -1), &window)
+            .unwrap();
+        assert_eq!(vec![9, 0, 0], window.first_bytes(0));
+        assert_eq!(vec![0; 3], window.first_bytes(1));
+    }
+
+    #[test]
+    fn pixelbuffer_resize_preserve() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((4, 4), (2, 2));
+        for row in pb.rows_mut() {
+            row.fill(BGR::from_rgb(9, 9, 9));
+        }
+
+        // Growing reallocates, but keeps the old pixels.
+        pb.resize(3, 1, ResizeContents::Preserve).unwrap();
+        assert_eq!(12, pb.row_len());
+        pb.resize(1, 3, ResizeContents::Preserve).unwrap();
+        assert_eq!((1, 3), (pb.width(), pb.height()));
+        assert_eq!(&[BGR::from_rgb(9, 9, 9)][..], pb.row(0).unwrap());
+        assert_eq!(&[BGR::from_rgb(0, 0, 0)][..], pb.row(1).unwrap());
+
+        // Growing back within the capacity zeroes the pixels that were cut off.
+        pb.resize(3, 3, ResizeContents::Preserve).unwrap();
+        assert_eq!(12, pb.row_len());
+        let expected = [
+            BGR::from_rgb(9, 9, 9),
+            BGR::from_rgb(0, 0, 0),
+            BGR::from_rgb(0, 0, 0),
+        ];
+        assert_eq!(&expected[..], pb.row(0).unwrap());
+        assert!(pb
+            .rows()
+            .skip(1)
+            .flatten()
+            .all(|&p| p == BGR::from_rgb(0, 0, 0)));
+
+        pb.blit(&window).unwrap();
+        assert_eq!(vec![9, 0, 0, 0], window.first_bytes(0));
+    }
+
+    #[test]
+    fn pixelbuffer_resize_clear() {
+        let (_, mut pb) = HeadlessWindow::with_buffer::<BGRA>((4, 4), (4, 4));
+        for row in pb.rows_mut() {
+            row.fill(BGRA::from_rgb(9, 9, 9));
+        }
+        pb.resize(2, 3, ResizeContents::Clear).unwrap();
+        assert_eq!(3, pb.rows().len());
+        assert!(pb.rows().flatten().all(|&p| p == BGRA::new(0, 0, 0, 0)));
+
+        assert!(matches!(
+            pb.resize(u32::MAX, 1, ResizeContents::Clear),
+            Err(BlitError::DimensionsTooLarge)
+        ));
+        assert_eq!((2, 3), (pb.width(), pb.height()));
+    }
+
+    #[test]
+    fn pixelbuffer_from_slice() {
+        let window = HeadlessWindow::new(3, 3, PixelBufferFormatType::BGR);
+        // Two 2x2 images, with rows 8 bytes apart.
+        let data = [1, 1, 1, 2, 2, 2, 0, 0, 3, 3, 3, 4, 4, 4];
+        let pb = PixelBuffer::from_slice(&data, 2, 2, 8, PixelBufferFormatType::BGR, &window);
+        let mut pb = pb.unwrap();
+        assert_eq!(&[3, 3, 3, 4, 4, 4][..], pb.row(1).unwrap());
+
+        let small = PixelBuffer::from_slice(&data, 2, 3, 8, PixelBufferFormatType::BGR, &window);
+        assert!(matches!(small, Err(BlitError::SliceTooSmall)));
+        let wrong_format = pb.blit_from_slice(&window, &data, 2, 2, 8, PixelBufferFormatType::RGB);
+        assert!(matches!(wrong_format, Err(BlitError::FormatNotSupported)));
+
+        // The image gets presented without touching the buffer's contents.
+        pb.row_mut(0).unwrap().fill(9);
+        let image = [5; 4 * 3 * 3];
+        pb.blit_from_slice(&window, &image, 4, 3, 12, PixelBufferFormatType::BGR)
+            .unwrap();
+        assert_eq!(vec![5; 3], window.first_bytes(2));
+        assert_eq!(&[9; 6][..], pb.row(0).unwrap());
+    }
+
+    #[test]
+    fn pixelbuffer_as_bytes() {
+        let window = HeadlessWindow::new(3, 2, PixelBufferFormatType::BGR);
+        let mut pb = PixelBuffer::new(3, 2, PixelBufferFormatType::BGR, &window).unwrap();
+        let row_len = pb.row_len();
+        assert_eq!(row_len * 2, pb.as_bytes().len());
+
+        let bytes = pb.as_bytes_mut();
+        bytes[..9].copy_from_slice(&[1; 9]);
+        bytes[row_len..row_len + 9].copy_from_slice(&[2; 9]);
+        assert_eq!(&[1; 9][..], pb.row(0).unwrap());
+        assert_eq!(&[2; 9][..], pb.row(1).unwrap());
+        assert_eq!(
+            vec![&[2; 9][..], &[1; 9][..]],
+            pb.rows().rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn pixelbuffer_straight_alpha() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGRA>((2, 1), (2, 1));
+        pb.set_alpha_mode(AlphaMode::Straight).unwrap();
+        let pixels = [BGRA::new(200, 100, 50, 128), BGRA::new(10, 20, 30, 255)];
+        pb.row_mut(0).unwrap().copy_from_slice(&pixels);
+
+        // The window gets premultiplied pixels, while the buffer keeps its straight ones.
+        pb.blit(&window).unwrap();
+        assert_eq!(
+            &[100, 50, 25, 128, 10, 20, 30, 255][..],
+            &*window.row(0).unwrap()
+        );
+        assert_eq!(&pixels[..], pb.row(0).unwrap());
+
+        pb.set_alpha_mode(AlphaMode::Premultiplied).unwrap();
+        pb.blit(&window).unwrap();
+        assert_eq!(&[200, 100, 50, 128][..], &window.row(0).unwrap()[..4]);
+
+        let other = HeadlessWindow::new(2, 1, PixelBufferFormatType::BGRA);
+        pb.set_alpha_mode(AlphaMode::Straight).unwrap();
+        assert!(matches!(pb.blit(&other), Err(BlitError::WindowMismatch)));
+
+        let (_, mut pb) = HeadlessWindow::with_buffer::<BGR>((2, 1), (2, 1));
+        let result = pb.set_alpha_mode(AlphaMode::Premultiplied);
+        assert!(matches!(result, Err(BlitError::FormatNotSupported)));
+        assert_eq!(AlphaMode::Opaque, pb.alpha_mode());
+    }
+
+    #[test]
+    fn pixelbuffer_scale_factor() {
+        let window = HeadlessWindow::new(4, 4, PixelBufferFormatType::BGR);
+        let pb = PixelBufferTyped::<BGR>::from_logical_size(101.0, 33.0, 1.25, &window).unwrap();
+        assert_eq!((126, 41), (pb.width(), pb.height()));
+        assert_eq!((100.8, 32.8), pb.logical_size());
+        assert_eq!((12.5, 5.0), pb.to_physical(10.0, 4.0));
+
+        let mut pb = PixelBufferTyped::<BGR>::from_logical_size(101.0, 33.0, 1.5, &window).unwrap();
+        assert_eq!((152, 50), (pb.width(), pb.height()));
+        assert_eq!((2.0, 0.5), pb.to_logical(3.0, 0.75));
+        pb.set_scale_factor(2.0).unwrap();
+        assert_eq!((76.0, 25.0), pb.logical_size());
+
+        let negative = PixelBufferTyped::<BGR>::from_logical_size(-2.0, 1.0, 1.5, &window);
+        assert!(matches!(negative, Err(BlitError::DimensionsTooLarge)));
+
+        for invalid in [f64::NAN, 0.0, -1.5, f64::INFINITY, f64::NEG_INFINITY] {
+            let created = PixelBufferTyped::<BGR>::from_logical_size(1.0, 1.0, invalid, &window);
+            assert!(matches!(created, Err(BlitError::InvalidScaleFactor)));
+            let result = pb.set_scale_factor(invalid);
+            assert!(matches!(result, Err(BlitError::InvalidScaleFactor)));
+            assert_eq!(2.0, pb.scale_factor());
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn pixelbuffer_par_tiles_mut() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((5, 3), (5, 3));
+        pb.set_damage_tracking(true);
+        pb.blit_damaged(&window).unwrap();
+
+        let mut tiles: Vec<_> = pb
+            .par_tiles_mut(2, 2)
+            .map(|((x, y), mut tile)| {
+                let shade = (x + 10 * y) as u8;
+                tile.fill(BGR::from_rgb(shade, shade, shade));
+                ((x, y), (tile.width(), tile.height()))
+            })
+            .collect();
+        tiles.sort();
+        let expected = [
+            ((0, 0), (2, 2)),
+            ((0, 2), (2, 1)),
+            ((2, 0), (2, 2)),
+            ((2, 2), (2, 1)),
+            ((4, 0), (1, 2)),
+            ((4, 2), (1, 1)),
+        ];
+        assert_eq!(&expected[..], &tiles[..]);
+        assert_eq!(&[Rect::from_size(5, 3)][..], pb.damage());
+
+        pb.blit_damaged(&window).unwrap();
+        assert_eq!(vec![0, 0, 2, 2, 4], window.first_bytes(1));
+        assert_eq!(vec![20, 20, 22, 22, 24], window.first_bytes(2));
+    }
+}
@@ -0,0 +1,194 @@
+//! Pixel buffers stored in regular memory, for targets that aren't native windows.
+
+use crate::PixelBufferFormatType;
+
+/// The dimensions and memory layout of a top-down image.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub width: u32,
+    pub height: u32,
+    /// The length, in bytes, of a single row.
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+}
+
+impl Layout {
+    /// The layout used for a `width` by `height` image in `format`, with each row padded to a
+    /// multiple of four bytes.
+    pub fn new(width: u32, height: u32, format: PixelBufferFormatType) -> Layout {
+        let bytes_per_pixel = match format {
+            PixelBufferFormatType::BGR | PixelBufferFormatType::RGB => 3,
+            PixelBufferFormatType::BGRA | PixelBufferFormatType::RGBA => 4,
+        };
+        Layout {
+            width: 0,
+            height: 0,
+            stride: 0,
+            bytes_per_pixel,
+        }
+        .resized(width, height)
+    }
+
+    /// The layout for an image of the same format, but a different size.
+    pub fn resized(&self, width: u32, height: u32) -> Layout {
+        Layout {
+            width,
+            height,
+            stride: (width as usize * self.bytes_per_pixel + 3) & !3,
+            bytes_per_pixel: self.bytes_per_pixel,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stride * self.height as usize
+    }
+}
+
+/// The red, green and blue channels of the first pixel in `pixel`, which is in `format`.
+pub(crate) fn rgb(format: PixelBufferFormatType, pixel: &[u8]) -> (u8, u8, u8) {
+    match format {
+        PixelBufferFormatType::BGR | PixelBufferFormatType::BGRA => (pixel[2], pixel[1], pixel[0]),
+        PixelBufferFormatType::RGB | PixelBufferFormatType::RGBA => (pixel[0], pixel[1], pixel[2]),
+    }
+}
+
+/// Copies the `size` pixels at `src_pos` in `src` to `dst_pos` in `dst`.
+///
+/// The rectangle gets clipped to the bounds of both images.
+pub(crate) fn copy_rect(
+    src: &[u8],
+    src_layout: Layout,
+    src_pos: (u32, u32),
+    dst: &mut [u8],
+    dst_layout: Layout,
+    dst_pos: (u32, u32),
+    size: (u32, u32),
+) {
+    assert_eq!(src_layout.bytes_per_pixel, dst_layout.bytes_per_pixel);
+    let bytes_per_pixel = src_layout.bytes_per_pixel;
+
+    let clip = |pos: (u32, u32), layout: Layout| {
+        (
+            size.0.min(layout.width.saturating_sub(pos.0)),
+            size.1.min(layout.height.saturating_sub(pos.1)),
+        )
+    };
+    let (src_w, src_h) = clip(src_pos, src_layout);
+    let (dst_w, dst_h) = clip(dst_pos, dst_layout);
+    let row_len = src_w.min(dst_w) as usize * bytes_per_pixel;
+
+    for row in 0..src_h.min(dst_h) as usize {
+        let src_start =
+            (src_pos.1 as usize + row) * src_layout.stride + src_pos.0 as usize * bytes_per_pixel;
+        let dst_start =
+            (dst_pos.1 as usize + row) * dst_layout.stride + dst_pos.0 as usize * bytes_per_pixel;
+        dst[dst_start..dst_start + row_len].copy_from_slice(&src[src_start..src_start + row_len]);
+    }
+}
+
+pub struct PixelBuffer {
+    data: Vec<u8>,
+    layout: Layout,
+    /// The size the buffer's memory was allocated for, which may be larger than `layout`.
+    capacity: (u32, u32),
+    /// The ID of the target the buffer was created for.
+    target_id: u64,
+}
+
+impl PixelBuffer {
+    pub fn new(width: u32, height: u32, format: PixelBufferFormatType, target_id: u64) -> Self {
+        let layout = Layout::new(width, height, format);
+        PixelBuffer {
+            data: vec![0; layout.len()],
+            layout,
+            capacity: (width, height),
+            target_id,
+        }
+    }
+
+    pub fn capacity(&self) -> (u32, u32) {
+        self.capacity
+    }
+
+    /// Changes the buffer's size without touching its memory. The size must fit in `capacity`.
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.layout.width = width;
+        self.layout.height = height;
+    }
+
+    /// Replaces the buffer's memory with zeroed memory of the given size.
+    pub fn reallocate(&mut self, width: u32, height: u32) {
+        self.layout = self.layout.resized(width, height);
+        self.data.clear();
+        self.data.resize(self.layout.len(), 0);
+        self.capacity = (width, height);
+    }
+
+    pub fn target_id(&self) -> u64 {
+        self.target_id
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.layout.bytes_per_pixel * 8
+    }
+
+    pub fn width(&self) -> u32 {
+        self.layout.width
+    }
+
+    pub fn row_len(&self) -> usize {
+        self.layout.stride
+    }
+
+    pub fn height(&self) -> u32 {
+        self.layout.height
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.layout.len()]
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.layout.len();
+        &mut self.data[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_pads_rows() {
+        let layout = Layout::new(5, 3, PixelBufferFormatType::BGR);
+        assert_eq!(16, layout.stride);
+        assert_eq!(48, layout.len());
+
+        let layout = Layout::new(5, 3, PixelBufferFormatType::RGBA);
+        assert_eq!(20, layout.stride);
+    }
+
+    #[test]
+    fn copy_rect_clips() {
+        let layout = Layout::new(4, 4, PixelBufferFormatType::BGRA);
+        let src: Vec<u8> = (0..16).flat_map(|i| [i as u8; 4]).collect();
+        let mut dst = vec![0xff; layout.len()];
+        copy_rect(&src, layout, (0, 1), &mut dst, layout, (2, 3), (3, 3));
+
+        let pixels: Vec<u8> = dst.chunks(4).map(|p| p[0]).collect();
+        #[rustfmt::skip]
+        assert_eq!(
+            pixels,
+            [
+                0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff, 0xff, 0xff,
+                0xff, 0xff,    4,    5,
+            ]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{BlitError, BlitTarget, PixelBufferFormat, PixelBufferTyped, Rect, ResizeContents};
+use std::mem;
+
+/// A pixel buffer with a fixed logical resolution, presented at the largest whole-number scale
+/// that fits the window.
+///
+/// The image gets centered in the window, and the rest of the window is filled with a border
+/// color. This keeps every logical pixel the same size on screen, as emulators and pixel-art games
+/// want. If the window is smaller than the logical resolution, the image is presented at its
+/// original size, with its edges cut off.
+///
+/// Each present scales the image into a frame the size of the window, borders included, and
+/// blits that in one go. Wayland surfaces take on the size of whatever gets attached to them, so
+/// the window's size has to come from the caller, such as from winit's `inner_size`.
+pub struct PixelPerfectSurface<P: PixelBufferFormat> {
+    buffer: PixelBufferTyped<P>,
+    border_color: P,
+    /// The window's size as of the last present, and the size of `frame`.
+    window_size: (u32, u32),
+    /// The image scaled up and surrounded by borders, as last presented.
+    frame: PixelBufferTyped<P>,
+    /// Whether the frame's borders need filling with the border color again.
+    stale_borders: bool,
+}
+
+impl<P: PixelBufferFormat> PixelPerfectSurface<P> {
+    /// Creates a surface with the given logical resolution for `window`, with black borders.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::new`].
+    pub fn new<H: BlitTarget>(
+        logical_width: u32,
+        logical_height: u32,
+        window: &H,
+    ) -> Result<PixelPerfectSurface<P>, BlitError> {
+        Ok(PixelPerfectSurface {
+            buffer: PixelBufferTyped::new(logical_width, logical_height, window)?,
+            border_color: P::from_rgb(0, 0, 0),
+            window_size: (logical_width, logical_height),
+            frame: PixelBufferTyped::new(logical_width, logical_height, window)?,
+            stale_borders: true,
+        })
+    }
+
+    /// The buffer holding the image, at the logical resolution.
+    pub fn buffer(&self) -> &PixelBufferTyped<P> {
+        &self.buffer
+    }
+
+    /// Mutably gets the buffer holding the image, at the logical resolution.
+    pub fn buffer_mut(&mut self) -> &mut PixelBufferTyped<P> {
+        &mut self.buffer
+    }
+
+    /// The logical resolution.
+    pub fn logical_size(&self) -> (u32, u32) {
+        (self.buffer.width(), self.buffer.height())
+    }
+
+    /// The color the window gets filled with around the image.
+    pub fn border_color(&self) -> P {
+        self.border_color
+    }
+
+    /// Sets the color the window gets filled with around the image.
+    pub fn set_border_color(&mut self, color: P) {
+        self.border_color = color;
+        self.stale_borders = true;
+    }
+
+    /// The size, in physical pixels, of the window's client area, as passed to the last
+    /// [`present`](Self::present). Before the first present, this is the logical resolution.
+    ///
+    /// [`scale`](Self::scale), [`viewport`](Self::viewport) and
+    /// [`window_to_logical`](Self::window_to_logical) are all based on this size.
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    /// How many window pixels each logical pixel covers, along each axis. Never less than `1`.
+    pub fn scale(&self) -> u32 {
+        let (width, height) = self.logical_size();
+        let scale_x = self.window_size.0.checked_div(width).unwrap_or(u32::MAX);
+        let scale_y = self.window_size.1.checked_div(height).unwrap_or(u32::MAX);
+        scale_x.min(scale_y).clamp(1, i32::MAX as u32)
+    }
+
+    /// The rectangle of the window the image gets presented onto.
+    pub fn viewport(&self) -> Rect {
+        let scale = i64::from(self.scale());
+        let (width, height) = self.logical_size();
+        let size = (i64::from(width) * scale, i64::from(height) * scale);
+        let clamp = |n: i64| n.clamp(0, i64::from(i32::MAX));
+        Rect::new(
+            ((i64::from(self.window_size.0) - size.0) / 2).max(i64::from(i32::MIN)) as i32,
+            ((i64::from(self.window_size.1) - size.1) / 2).max(i64::from(i32::MIN)) as i32,
+            clamp(size.0) as u32,
+            clamp(size.1) as u32,
+        )
+    }
+
+    /// Maps a position in the window, such as the cursor's, to the logical pixel under it.
+    ///
+    /// Returns `None` if the position is outside of the image.
+    pub fn window_to_logical(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let viewport = self.viewport();
+        let scale = f64::from(self.scale());
+        let logical_x = ((x - f64::from(viewport.x)) / scale).floor();
+        let logical_y = ((y - f64::from(viewport.y)) / scale).floor();
+        let (width, height) = self.logical_size();
+        match (0.0..f64::from(width)).contains(&logical_x)
+            && (0.0..f64::from(height)).contains(&logical_y)
+        {
+            true => Some((logical_x as u32, logical_y as u32)),
+            false => None,
+        }
+    }
+
+    /// Presents the image onto `window`, whose client area is `window_size` physical pixels large,
+    /// surrounded by the border color.
+    ///
+    /// The image gets laid out for `window_size`, which also becomes the
+    /// [`window_size`](Self::window_size).
+    ///
+    /// # Errors
+    /// Returns [`BlitError::DimensionsTooLarge`] if a buffer of `window_size` can't be allocated,
+    /// and otherwise the same errors as [`PixelBufferTyped::blit`].
+    pub fn present<H: BlitTarget>(
+        &mut self,
+        window: &H,
+        window_size: (u32, u32),
+    ) -> Result<(), BlitError> {
+        if window_size != (self.frame.width(), self.frame.height()) {
+            self.frame
+                .resize(window_size.0, window_size.1, ResizeContents::Undefined)?;
+            self.stale_borders = true;
+        }
+        self.window_size = window_size;
+        if self.frame.alpha_mode() != self.buffer.alpha_mode() {
+            self.frame.set_alpha_mode(self.buffer.alpha_mode())?;
+        }
+        if mem::take(&mut self.stale_borders) {
+            self.frame.pixels_mut().fill(self.border_color);
+        }
+
+        let viewport = self.viewport();
+        let scale = i64::from(self.scale());
+        let frame_rect = Rect::from_size(
+            window_size.0.min(i32::MAX as u32),
+            window_size.1.min(i32::MAX as u32),
+        );
+        if let Some(visible) = viewport.intersection(&frame_rect) {
+            // The logical column of each visible column of the frame.
+            let columns: Vec<usize> = (visible.x..visible.right() as i32)
+                .map(|x| ((i64::from(x) - i64::from(viewport.x)) / scale) as usize)
+                .collect();
+            for y in visible.y..visible.bottom() as i32 {
+                let logical_y = (i64::from(y) - i64::from(viewport.y)) / scale;
+                let src = self.buffer.row(logical_y as u32).unwrap();
+                let dst = &mut self.frame.row_mut(y as u32).unwrap()[visible.x as usize..];
+                for (pixel, &x) in dst.iter_mut().zip(&columns) {
+                    *pixel = src[x];
+                }
+            }
+        }
+        self.frame.blit(window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadlessWindow, PixelBufferFormatType, BGR};
+
+    #[test]
+    fn pixel_perfect_letterboxes() {
+        let window = HeadlessWindow::new(7, 5, PixelBufferFormatType::BGR);
+        let mut surface = PixelPerfectSurface::<BGR>::new(2, 2, &window).unwrap();
+        surface.set_border_color(BGR::new(9, 9, 9));
+        surface.buffer_mut().row_mut(1).unwrap()[1] = BGR::new(1, 1, 1);
+        assert_eq!((2, 2), surface.window_size());
+
+        surface.present(&window, (7, 5)).unwrap();
+        assert_eq!((7, 5), surface.window_size());
+        assert_eq!(2, surface.scale());
+        assert_eq!(Rect::new(1, 0, 4, 4), surface.viewport());
+        assert_eq!(vec![9, 0, 0, 0, 0, 9, 9], window.first_bytes(0));
+        assert_eq!(vec![9, 0, 0, 1, 1, 9, 9], window.first_bytes(3));
+        assert_eq!(vec![9; 7], window.first_bytes(4));
+
+        assert_eq!(Some((0, 0)), surface.window_to_logical(1.5, 0.0));
+        assert_eq!(Some((1, 1)), surface.window_to_logical(4.9, 3.9));
+        assert_eq!(None, surface.window_to_logical(0.5, 0.0));
+        assert_eq!(None, surface.window_to_logical(5.0, 2.0));
+
+        // Windows smaller than the logical resolution crop the image.
+        let small = HeadlessWindow::new(1, 1, PixelBufferFormatType::BGR);
+        let mut surface = PixelPerfectSurface::<BGR>::new(2, 2, &small).unwrap();
+        surface.present(&small, (1, 1)).unwrap();
+        assert_eq!(1, surface.scale());
+        assert_eq!(Rect::new(0, 0, 2, 2), surface.viewport());
+    }
+
+    #[test]
+    fn pixel_perfect_uses_given_window_size() {
+        let (window, _) = HeadlessWindow::with_buffer::<BGR>((7, 5), (0, 0));
+        let mut surface = PixelPerfectSurface::<BGR>::new(2, 2, &window).unwrap();
+        surface.set_border_color(BGR::new(9, 9, 9));
+        surface.buffer_mut().row_mut(0).unwrap()[0] = BGR::new(1, 1, 1);
+
+        // Only the given size gets laid out and drawn onto, whatever the window's actual size.
+        surface.present(&window, (4, 3)).unwrap();
+        assert_eq!(1, surface.scale());
+        assert_eq!(Rect::new(1, 0, 2, 2), surface.viewport());
+        assert_eq!(vec![9, 1, 0, 9, 0, 0, 0], window.first_bytes(0));
+        assert_eq!(vec![9; 4], window.first_bytes(2)[..4]);
+        assert_eq!(vec![0; 7], window.first_bytes(3));
+
+        // Growing the window lays the image out again, with fresh borders.
+        surface.present(&window, (7, 5)).unwrap();
+        assert_eq!(2, surface.scale());
+        assert_eq!(vec![9, 1, 1, 0, 0, 9, 9], window.first_bytes(1));
+        assert_eq!(vec![9; 7], window.first_bytes(4));
+    }
+}
@@ -0,0 +1,648 @@
+//! Views over the pixels of a typed pixel buffer.
+
+use crate::{rect::Rect, PixelBufferFormat};
+use std::{marker::PhantomData, mem, slice};
+
+/// A 2D view over every pixel in a [`PixelBufferTyped`](crate::PixelBufferTyped).
+///
+/// Rows may be padded, so the pixels are only available as a single slice through
+/// [`as_slice`](Self::as_slice) when there's no padding.
+#[derive(Debug, Clone, Copy)]
+pub struct Pixels<'a, P: PixelBufferFormat> {
+    bytes: &'a [u8],
+    width: u32,
+    height: u32,
+    row_len: usize,
+    _format: PhantomData<P>,
+}
+
+/// A mutable 2D view over every pixel in a [`PixelBufferTyped`](crate::PixelBufferTyped).
+#[derive(Debug)]
+pub struct PixelsMut<'a, P: PixelBufferFormat> {
+    bytes: &'a mut [u8],
+    width: u32,
+    height: u32,
+    row_len: usize,
+    _format: PhantomData<P>,
+}
+
+/// The length, in bytes, of the pixels in a row of `width` pixels.
+fn pixel_len<P: PixelBufferFormat>(width: u32) -> usize {
+    width as usize * mem::size_of::<P>()
+}
+
+/// The stride to chunk rows with. Never zero, so that chunking doesn't panic.
+fn chunk_len(row_len: usize) -> usize {
+    row_len.max(1)
+}
+
+impl<'a, P: PixelBufferFormat + 'a> Pixels<'a, P> {
+    /// `bytes` must hold `height` rows, each `row_len` bytes long.
+    pub(crate) fn new(bytes: &'a [u8], width: u32, height: u32, row_len: usize) -> Self {
+        debug_assert!(bytes.len() >= row_len * height as usize);
+        Pixels {
+            bytes,
+            width,
+            height,
+            row_len,
+            _format: PhantomData,
+        }
+    }
+
+    /// The width, in pixels, of the view.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height, in pixels, of the view.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The distance, in pixels, between the start of two rows, if it's a whole number of pixels.
+    pub fn stride(&self) -> Option<usize> {
+        match self.row_len % mem::size_of::<P>() {
+            0 => Some(self.row_len / mem::size_of::<P>()),
+            _ => None,
+        }
+    }
+
+    /// Gets the pixel at `(x, y)`, or `None` if it's out of bounds.
+    pub fn get(&self, x: u32, y: u32) -> Option<&'a P> {
+        self.row(y)?.get(x as usize)
+    }
+
+    /// Gets the row at the particular height.
+    pub fn row(&self, y: u32) -> Option<&'a [P]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y as usize * self.row_len;
+        Some(P::from_raw_slice(
+            &self.bytes[start..start + pixel_len::<P>(self.width)],
+        ))
+    }
+
+    /// Iterate through all rows, from top to bottom.
+    pub fn rows(&self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a [P]> {
+        let pixel_len = pixel_len::<P>(self.width);
+        self.bytes
+            .chunks(chunk_len(self.row_len))
+            .take(self.height as usize)
+            .map(move |row| P::from_raw_slice(&row[..pixel_len]))
+    }
+
+    /// Iterate through all pixels, along with their coordinates, row by row.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (u32, u32, &'a P)> {
+        self.rows().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, pixel)| (x as u32, y as u32, pixel))
+        })
+    }
+
+    /// All pixels as a single slice, if rows aren't padded.
+    pub fn as_slice(&self) -> Option<&'a [P]> {
+        if self.row_len != pixel_len::<P>(self.width) {
+            return None;
+        }
+        let len = self.row_len * self.height as usize;
+        Some(P::from_raw_slice(&self.bytes[..len]))
+    }
+}
+
+impl<'a, P: PixelBufferFormat> PixelsMut<'a, P> {
+    /// `bytes` must hold `height` rows, each `row_len` bytes long.
+    pub(crate) fn new(bytes: &'a mut [u8], width: u32, height: u32, row_len: usize) -> Self {
+        debug_assert!(bytes.len() >= row_len * height as usize);
+        PixelsMut {
+            bytes,
+            width,
+            height,
+            row_len,
+            _format: PhantomData,
+        }
+    }
+
+    /// Reborrows the view as an immutable one.
+    pub fn as_pixels(&self) -> Pixels<'_, P> {
+        Pixels::new(self.bytes, self.width, self.height, self.row_len)
+    }
+
+    /// The width, in pixels, of the view.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height, in pixels, of the view.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The distance, in pixels, between the start of two rows, if it's a whole number of pixels.
+    pub fn stride(&self) -> Option<usize> {
+        self.as_pixels().stride()
+    }
+
+    /// Gets the pixel at `(x, y)`, or `None` if it's out of bounds.
+    pub fn get(&self, x: u32, y: u32) -> Option<&P> {
+        self.as_pixels().get(x, y)
+    }
+
+    /// Mutably gets the pixel at `(x, y)`, or `None` if it's out of bounds.
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        self.row_mut(y)?.get_mut(x as usize)
+    }
+
+    /// Mutably gets the row at the particular height.
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut [P]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y as usize * self.row_len;
+        Some(P::from_raw_slice_mut(
+            &mut self.bytes[start..start + pixel_len::<P>(self.width)],
+        ))
+    }
+
+    /// Mutably iterate through all rows, from top to bottom.
+    pub fn rows_mut(&mut self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &mut [P]> {
+        let pixel_len = pixel_len::<P>(self.width);
+        self.bytes
+            .chunks_mut(chunk_len(self.row_len))
+            .take(self.height as usize)
+            .map(move |row| P::from_raw_slice_mut(&mut row[..pixel_len]))
+    }
+
+    /// Mutably iterate through all pixels, along with their coordinates, row by row.
+    pub fn enumerate_pixels(&mut self) -> impl Iterator<Item = (u32, u32, &mut P)> {
+        self.rows_mut().enumerate().flat_map(|(y, row)| {
+            row.iter_mut()
+                .enumerate()
+                .map(move |(x, pixel)| (x as u32, y as u32, pixel))
+        })
+    }
+
+    /// Sets every pixel to `pixel`.
+    pub fn fill(&mut self, pixel: P) {
+        match self.as_mut_slice() {
+            Some(pixels) => pixels.fill(pixel),
+            None => self.rows_mut().for_each(|row| row.fill(pixel)),
+        }
+    }
+
+    /// All pixels as a single slice, if rows aren't padded.
+    pub fn as_slice(&self) -> Option<&[P]> {
+        self.as_pixels().as_slice()
+    }
+
+    /// All pixels as a single mutable slice, if rows aren't padded.
+    pub fn as_mut_slice(&mut self) -> Option<&mut [P]> {
+        if self.row_len != pixel_len::<P>(self.width) {
+            return None;
+        }
+        let len = self.row_len * self.height as usize;
+        Some(P::from_raw_slice_mut(&mut self.bytes[..len]))
+    }
+}
+
+/// A view over a rectangle of pixels in a [`PixelBufferTyped`](crate::PixelBufferTyped).
+///
+/// Views can be split into smaller views, and are [`Send`] and [`Sync`], so different parts of a
+/// buffer can be read from different threads.
+#[derive(Debug)]
+pub struct PixelView<'a, P: PixelBufferFormat> {
+    /// The first byte of the view's top-left pixel.
+    ptr: *const u8,
+    width: u32,
+    height: u32,
+    row_len: usize,
+    _pixels: PhantomData<&'a [P]>,
+}
+
+/// A mutable view over a rectangle of pixels in a [`PixelBufferTyped`](crate::PixelBufferTyped).
+///
+/// Views can be split into disjoint views with [`split_at_row_mut`](Self::split_at_row_mut) and
+/// [`split_at_column_mut`](Self::split_at_column_mut), which can then be drawn into from
+/// different threads.
+#[derive(Debug)]
+pub struct PixelViewMut<'a, P: PixelBufferFormat> {
+    /// The first byte of the view's top-left pixel.
+    ptr: *mut u8,
+    width: u32,
+    height: u32,
+    row_len: usize,
+    _pixels: PhantomData<&'a mut [P]>,
+}
+
+// SAFETY: views behave like the slices of pixels they borrow.
+unsafe impl<P: PixelBufferFormat + Sync> Send for PixelView<'_, P> {}
+unsafe impl<P: PixelBufferFormat + Sync> Sync for PixelView<'_, P> {}
+unsafe impl<P: PixelBufferFormat + Send> Send for PixelViewMut<'_, P> {}
+unsafe impl<P: PixelBufferFormat + Sync> Sync for PixelViewMut<'_, P> {}
+
+impl<P: PixelBufferFormat> Clone for PixelView<'_, P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<P: PixelBufferFormat> Copy for PixelView<'_, P> {}
+
+/// Clips `rect` to a view of the given size.
+fn clip(rect: Rect, width: u32, height: u32) -> Rect {
+    let clamp = |n: u32| n.min(i32::MAX as u32);
+    rect.intersection(&Rect::from_size(clamp(width), clamp(height)))
+        .unwrap_or_default()
+}
+
+/// The offset, in bytes, of the pixel at `(x, y)`.
+fn offset<P: PixelBufferFormat>(x: u32, y: u32, row_len: usize) -> usize {
+    y as usize * row_len + pixel_len::<P>(x)
+}
+
+impl<'a, P: PixelBufferFormat + 'a> PixelView<'a, P> {
+    /// A view of `rect`, clipped to the `width` by `height` pixels stored in `bytes`, with rows
+    /// `row_len` bytes apart.
+    pub(crate) fn new(
+        bytes: &'a [u8],
+        width: u32,
+        height: u32,
+        row_len: usize,
+        rect: Rect,
+    ) -> Self {
+        assert!(bytes.len() >= row_len * height as usize);
+        assert!(row_len >= pixel_len::<P>(width));
+        let rect = clip(rect, width, height);
+        PixelView {
+            ptr: bytes[offset::<P>(rect.x as u32, rect.y as u32, row_len)..].as_ptr(),
+            width: rect.width,
+            height: rect.height,
+            row_len,
+            _pixels: PhantomData,
+        }
+    }
+
+    /// The width, in pixels, of the view.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height, in pixels, of the view.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Gets the row at the particular height.
+    pub fn row(&self, y: u32) -> Option<&'a [P]> {
+        if y >= self.height {
+            return None;
+        }
+        // SAFETY: the row lies within the view, which the view borrows.
+        let row = unsafe {
+            slice::from_raw_parts(
+                self.ptr.add(offset::<P>(0, y, self.row_len)),
+                pixel_len::<P>(self.width),
+            )
+        };
+        Some(P::from_raw_slice(row))
+    }
+
+    /// Iterate through all rows in the view, from top to bottom.
+    pub fn rows(&self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a [P]> {
+        let view = *self;
+        (0..self.height).map(move |y| view.row(y).unwrap())
+    }
+
+    /// A view of `rect` within this view. The rectangle is relative to the view, and gets clipped
+    /// to it.
+    pub fn view(&self, rect: Rect) -> PixelView<'a, P> {
+        let rect = clip(rect, self.width, self.height);
+        PixelView {
+            ptr: self
+                .ptr
+                .wrapping_add(offset::<P>(rect.x as u32, rect.y as u32, self.row_len)),
+            width: rect.width,
+            height: rect.height,
+            row_len: self.row_len,
+            _pixels: PhantomData,
+        }
+    }
+
+    /// Splits the view into the rows above `y` and the rows from `y` on.
+    ///
+    /// # Panics
+    /// Panics if `y` is greater than the view's height.
+    pub fn split_at_row(&self, y: u32) -> (PixelView<'a, P>, PixelView<'a, P>) {
+        assert!(y <= self.height, "split row out of bounds");
+        (
+            self.view(Rect::new(0, 0, self.width, y)),
+            PixelView {
+                ptr: self.ptr.wrapping_add(offset::<P>(0, y, self.row_len)),
+                height: self.height - y,
+                ..*self
+            },
+        )
+    }
+
+    /// Splits the view into the columns left of `x` and the columns from `x` on.
+    ///
+    /// # Panics
+    /// Panics if `x` is greater than the view's width.
+    pub fn split_at_column(&self, x: u32) -> (PixelView<'a, P>, PixelView<'a, P>) {
+        assert!(x <= self.width, "split column out of bounds");
+        (
+            PixelView { width: x, ..*self },
+            PixelView {
+                ptr: self.ptr.wrapping_add(offset::<P>(x, 0, self.row_len)),
+                width: self.width - x,
+                ..*self
+            },
+        )
+    }
+}
+
+impl<'a, P: PixelBufferFormat + 'a> PixelViewMut<'a, P> {
+    /// A mutable view of `rect`, clipped to the `width` by `height` pixels stored in `bytes`,
+    /// with rows `row_len` bytes apart.
+    pub(crate) fn new(
+        bytes: &'a mut [u8],
+        width: u32,
+        height: u32,
+        row_len: usize,
+        rect: Rect,
+    ) -> Self {
+        assert!(bytes.len() >= row_len * height as usize);
+        assert!(row_len >= pixel_len::<P>(width));
+        let rect = clip(rect, width, height);
+        PixelViewMut {
+            ptr: bytes[offset::<P>(rect.x as u32, rect.y as u32, row_len)..].as_mut_ptr(),
+            width: rect.width,
+            height: rect.height,
+            row_len,
+            _pixels: PhantomData,
+        }
+    }
+
+    /// Reborrows the view as an immutable one.
+    pub fn as_view(&self) -> PixelView<'_, P> {
+        PixelView {
+            ptr: self.ptr,
+            width: self.width,
+            height: self.height,
+            row_len: self.row_len,
+            _pixels: PhantomData,
+        }
+    }
+
+    /// Reborrows the view, so that it can be split without giving it up.
+    pub fn reborrow(&mut self) -> PixelViewMut<'_, P> {
+        PixelViewMut {
+            _pixels: PhantomData,
+            ..*self
+        }
+    }
+
+    /// The width, in pixels, of the view.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height, in pixels, of the view.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Gets the row at the particular height.
+    pub fn row(&self, y: u32) -> Option<&[P]> {
+        self.as_view().row(y)
+    }
+
+    /// Mutably gets the row at the particular height.
+    pub fn row_mut(&mut self, y: u32) -> Option<&mut [P]> {
+        if y >= self.height {
+            return None;
+        }
+        // SAFETY: the row lies within the view, which the view borrows mutably.
+        let row = unsafe {
+            slice::from_raw_parts_mut(
+                self.ptr.add(offset::<P>(0, y, self.row_len)),
+                pixel_len::<P>(self.width),
+            )
+        };
+        Some(P::from_raw_slice_mut(row))
+    }
+
+    /// Iterate through all rows in the view, from top to bottom.
+    pub fn rows(&self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &[P]> {
+        self.as_view().rows()
+    }
+
+    /// Mutably iterate through all rows in the view, from top to bottom.
+    pub fn rows_mut(&mut self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &mut [P]> {
+        let (ptr, row_len, pixel_len) = (self.ptr, self.row_len, pixel_len::<P>(self.width));
+        (0..self.height).map(move |y| {
+            // SAFETY: rows are disjoint, and lie within the view, which is borrowed mutably for
+            // as long as the iterator.
+            let row = unsafe {
+                slice::from_raw_parts_mut(ptr.add(offset::<P>(0, y, row_len)), pixel_len)
+            };
+            P::from_raw_slice_mut(row)
+        })
+    }
+
+    /// Sets every pixel in the view to `pixel`.
+    pub fn fill(&mut self, pixel: P) {
+        self.rows_mut().for_each(|row| row.fill(pixel));
+    }
+
+    /// A mutable view of `rect` within this view. The rectangle is relative to the view, and gets
+    /// clipped to it.
+    pub fn view_mut(&mut self, rect: Rect) -> PixelViewMut<'_, P> {
+        self.reborrow().into_view_mut(rect)
+    }
+
+    /// Like [`view_mut`](Self::view_mut), but gives up this view to keep its lifetime.
+    pub fn into_view_mut(self, rect: Rect) -> PixelViewMut<'a, P> {
+        let rect = clip(rect, self.width, self.height);
+        PixelViewMut {
+            ptr: self
+                .ptr
+                .wrapping_add(offset::<P>(rect.x as u32, rect.y as u32, self.row_len)),
+            width: rect.width,
+            height: rect.height,
+            ..self
+        }
+    }
+
+    /// Splits the view into tiles of `tile_width` by `tile_height` pixels, along with their
+    /// positions within the view, row by row. Tiles on the right and bottom edges are smaller if
+    /// the view's size isn't a multiple of the tile size.
+    ///
+    /// # Panics
+    /// Panics if `tile_width` or `tile_height` is zero.
+    pub fn into_tiles(
+        self,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> impl ExactSizeIterator + DoubleEndedIterator<Item = ((u32, u32), PixelViewMut<'a, P>)>
+    {
+        self.collect_tiles(tile_width, tile_height).into_iter()
+    }
+
+    /// The tiles of [`into_tiles`](Self::into_tiles), in a `Vec` that parallel iterators can
+    /// split up.
+    pub(crate) fn collect_tiles(
+        self,
+        tile_width: u32,
+        tile_height: u32,
+    ) -> Vec<((u32, u32), PixelViewMut<'a, P>)> {
+        assert!(
+            tile_width != 0 && tile_height != 0,
+            "tile size must not be zero"
+        );
+        let mut tiles = Vec::new();
+        let (mut rest, mut y) = (self, 0);
+        while rest.height > 0 {
+            let band_height = tile_height.min(rest.height);
+            let (mut band, below) = rest.split_at_row_mut(band_height);
+            rest = below;
+            let mut x = 0;
+            while band.width > 0 {
+                let width = tile_width.min(band.width);
+                let (tile, right) = band.split_at_column_mut(width);
+                tiles.push(((x, y), tile));
+                band = right;
+                x += tile_width;
+            }
+            y += tile_height;
+        }
+        tiles
+    }
+
+    /// Splits the view into the rows above `y` and the rows from `y` on.
+    ///
+    /// # Panics
+    /// Panics if `y` is greater than the view's height.
+    pub fn split_at_row_mut(self, y: u32) -> (PixelViewMut<'a, P>, PixelViewMut<'a, P>) {
+        assert!(y <= self.height, "split row out of bounds");
+        (
+            PixelViewMut {
+                height: y,
+                _pixels: PhantomData,
+                ..self
+            },
+            PixelViewMut {
+                ptr: self.ptr.wrapping_add(offset::<P>(0, y, self.row_len)),
+                height: self.height - y,
+                ..self
+            },
+        )
+    }
+
+    /// Splits the view into the columns left of `x` and the columns from `x` on.
+    ///
+    /// # Panics
+    /// Panics if `x` is greater than the view's width.
+    pub fn split_at_column_mut(self, x: u32) -> (PixelViewMut<'a, P>, PixelViewMut<'a, P>) {
+        assert!(x <= self.width, "split column out of bounds");
+        (
+            PixelViewMut {
+                width: x,
+                _pixels: PhantomData,
+                ..self
+            },
+            PixelViewMut {
+                ptr: self.ptr.wrapping_add(offset::<P>(x, 0, self.row_len)),
+                width: self.width - x,
+                ..self
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BGR;
+
+    #[test]
+    fn pixels_padded_rows() {
+        // Two rows of two pixels, padded to 8 bytes.
+        let mut bytes = [0; 16];
+        let mut pixels = PixelsMut::<BGR>::new(&mut bytes, 2, 2, 8);
+        assert_eq!(None, pixels.stride());
+        assert!(pixels.as_mut_slice().is_none());
+
+        pixels.fill(BGR::new(1, 1, 1));
+        *pixels.get_mut(1, 1).unwrap() = BGR::new(2, 2, 2);
+        assert!(pixels.get_mut(2, 0).is_none());
+        for (x, y, pixel) in pixels.enumerate_pixels() {
+            pixel.r = (x + y * 2) as u8;
+        }
+        assert_eq!([1, 1, 0, 1, 1, 1, 0, 0, 1, 1, 2, 2, 2, 3, 0, 0], bytes);
+    }
+
+    #[test]
+    fn pixels_contiguous() {
+        let bytes = [1, 2, 3, 4, 5, 6];
+        let pixels = Pixels::<BGR>::new(&bytes, 1, 2, 3);
+        assert_eq!(Some(1), pixels.stride());
+        assert_eq!(
+            Some(&[BGR::new(1, 2, 3), BGR::new(4, 5, 6)][..]),
+            pixels.as_slice()
+        );
+        assert_eq!(Some(&BGR::new(4, 5, 6)), pixels.get(0, 1));
+    }
+
+    #[test]
+    fn pixel_view_split() {
+        // A 4x3 buffer, with rows padded to 16 bytes.
+        let mut bytes = [0; 16 * 3];
+        let view = PixelViewMut::<BGR>::new(&mut bytes, 4, 3, 16, Rect::new(-1, 1, 4, 5));
+        assert_eq!((3, 2), (view.width(), view.height()));
+
+        let (mut left, right) = view.split_at_column_mut(1);
+        let (mut top_right, mut bottom_right) = right.split_at_row_mut(1);
+        std::thread::scope(|s| {
+            s.spawn(|| left.fill(BGR::new(1, 1, 1)));
+            s.spawn(|| top_right.fill(BGR::new(2, 2, 2)));
+        });
+        bottom_right
+            .view_mut(Rect::new(1, 0, 5, 5))
+            .fill(BGR::new(3, 3, 3));
+        assert_eq!(
+            &[BGR::new(0, 0, 0), BGR::new(3, 3, 3)][..],
+            bottom_right.row(0).unwrap()
+        );
+        assert!(bottom_right.row(1).is_none());
+
+        let pixels = Pixels::<BGR>::new(&bytes, 4, 3, 16);
+        let rows: Vec<_> = pixels
+            .rows()
+            .map(|row| row.iter().map(|p| p.r).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(vec![vec![0; 4], vec![1, 2, 2, 0], vec![1, 0, 3, 0]], rows);
+    }
+
+    #[test]
+    fn pixel_view_tiles() {
+        let mut bytes = [0; 5 * 3 * 3];
+        let view = PixelViewMut::<BGR>::new(&mut bytes, 5, 3, 15, Rect::from_size(5, 3));
+        let tiles: Vec<_> = view.into_tiles(2, 2).collect();
+        let positions: Vec<_> = tiles.iter().map(|(pos, _)| *pos).collect();
+        assert_eq!(
+            vec![(0, 0), (2, 0), (4, 0), (0, 2), (2, 2), (4, 2)],
+            positions
+        );
+        let sizes: Vec<_> = tiles.iter().map(|(_, t)| (t.width(), t.height())).collect();
+        assert_eq!(vec![(2, 2), (2, 2), (1, 2), (2, 1), (2, 1), (1, 1)], sizes);
+
+        for (i, (_, mut tile)) in tiles.into_iter().enumerate() {
+            tile.fill(BGR::new(0, 0, i as u8));
+        }
+        let pixels = Pixels::<BGR>::new(&bytes, 5, 3, 15);
+        assert_eq!(Some(&BGR::new(0, 0, 2)), pixels.get(4, 1));
+        assert_eq!(Some(&BGR::new(0, 0, 4)), pixels.get(3, 2));
+    }
+}
@@ -0,0 +1,10 @@
+//! Platform-specific extensions to the pixel buffer types.
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub mod unix;
@@ -0,0 +1,33 @@
+use crate::{backend::Backend, PixelBuffer, PixelBufferFormat, PixelBufferTyped};
+
+/// Additional methods on pixel buffers that are specific to Unix platforms.
+pub trait PixelBufferExtUnix {
+    /// Returns `true` if the buffer's pixels live in memory shared with the display server.
+    ///
+    /// Always `false` for buffers created for a [`HeadlessWindow`](crate::HeadlessWindow).
+    ///
+    /// X11 buffers are shared through the MIT-SHM extension whenever the X server supports it,
+    /// which avoids copying the entire buffer over the X connection on every blit. They fall back
+    /// to plain `XPutImage` if the extension isn't available, which is always the case for remote
+    /// X servers.
+    ///
+    /// Always `false` on Wayland, where the compositor may keep reading a buffer after it's been
+    /// presented. Blits copy the blitted rectangles into separate `wl_shm` buffers instead, so that
+    /// the pixel buffer can be drawn into right away.
+    fn uses_shm(&self) -> bool;
+}
+
+impl PixelBufferExtUnix for PixelBuffer {
+    fn uses_shm(&self) -> bool {
+        match &self.p {
+            Backend::Native(p) => p.uses_shm(),
+            Backend::Memory(_) => false,
+        }
+    }
+}
+
+impl<P: PixelBufferFormat> PixelBufferExtUnix for PixelBufferTyped<P> {
+    fn uses_shm(&self) -> bool {
+        self.p.uses_shm()
+    }
+}
@@ -1,5 +1,15 @@
-pub use self::platform_impl::*;
+pub use self::platform::*;
 
 #[cfg(target_os = "windows")]
 #[path = "windows/mod.rs"]
-mod platform_impl;
+mod platform;
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+#[path = "unix/mod.rs"]
+mod platform;
@@ -0,0 +1,189 @@
+use crate::{
+    memory::Layout,
+    rect::{BlitArea, Rect},
+    scale::ScaleFilter,
+    BlitError, PixelBufferFormatSupported, PixelBufferFormatType,
+};
+use raw_window_handle::RawWindowHandle;
+
+mod wayland;
+mod x11;
+
+impl PixelBufferFormatSupported for crate::BGRA {}
+pub type NativeFormat = crate::BGRA;
+
+/// A pixel buffer for whichever display server the window belongs to.
+///
+/// Both backends store their pixels top-down.
+pub enum PixelBuffer {
+    X11(x11::PixelBuffer),
+    Wayland(wayland::PixelBuffer),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $p:ident => $e:expr) => {
+        match $self {
+            PixelBuffer::X11($p) => $e,
+            PixelBuffer::Wayland($p) => $e,
+        }
+    };
+}
+
+impl PixelBuffer {
+    pub unsafe fn new(
+        width: u32,
+        height: u32,
+        format: PixelBufferFormatType,
+        raw_window_handle: RawWindowHandle,
+    ) -> Result<PixelBuffer, BlitError> {
+        match raw_window_handle {
+            RawWindowHandle::Xlib(_) | RawWindowHandle::Xcb(_) => {
+                x11::PixelBuffer::new(width, height, format, raw_window_handle)
+                    .map(PixelBuffer::X11)
+            }
+            RawWindowHandle::Wayland(_) => {
+                wayland::PixelBuffer::new(width, height, format, raw_window_handle)
+                    .map(PixelBuffer::Wayland)
+            }
+            _ => Err(BlitError::UnsupportedHandle),
+        }
+    }
+
+    /// Checks that `handle` is of the same kind as the window the buffer was created for.
+    fn check_handle(&self, handle: RawWindowHandle) -> Result<(), BlitError> {
+        match (self, handle) {
+            (PixelBuffer::X11(_), RawWindowHandle::Xlib(_) | RawWindowHandle::Xcb(_))
+            | (PixelBuffer::Wayland(_), RawWindowHandle::Wayland(_)) => Ok(()),
+            (
+                _,
+                RawWindowHandle::Xlib(_) | RawWindowHandle::Xcb(_) | RawWindowHandle::Wayland(_),
+            ) => Err(BlitError::WindowMismatch),
+            _ => Err(BlitError::UnsupportedHandle),
+        }
+    }
+
+    pub unsafe fn window_size(&self, handle: RawWindowHandle) -> Result<(u32, u32), BlitError> {
+        self.check_handle(handle)?;
+        dispatch!(self, p => p.window_size(handle))
+    }
+
+    pub unsafe fn blit_rect(
+        &self,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        blit_size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<(), BlitError> {
+        self.check_handle(handle)?;
+        dispatch!(self, p => p.blit_rect(src_pos, dst_pos, blit_size, handle))
+    }
+
+    pub fn capacity(&self) -> (u32, u32) {
+        dispatch!(self, p => p.capacity())
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        dispatch!(self, p => p.set_size(width, height));
+        Ok(())
+    }
+
+    pub unsafe fn set_alpha(&mut self, alpha: bool) -> Result<(), BlitError> {
+        dispatch!(self, p => p.set_alpha(alpha))
+    }
+
+    pub unsafe fn reallocate(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        dispatch!(self, p => p.reallocate(width, height))
+    }
+
+    /// Blits several rectangles, as a single update if the display server supports it.
+    pub unsafe fn blit_rects(
+        &self,
+        areas: &[BlitArea],
+        handle: RawWindowHandle,
+    ) -> Result<(), BlitError> {
+        self.check_handle(handle)?;
+        match self {
+            PixelBuffer::X11(p) => {
+                for &(src_pos, dst_pos, size) in areas {
+                    p.blit_rect(src_pos, dst_pos, size, handle)?;
+                }
+                Ok(())
+            }
+            PixelBuffer::Wayland(p) => p.blit_rects(areas, handle),
+        }
+    }
+
+    /// Scales through the RENDER extension on X11, and `wp_viewporter` on Wayland, if the display
+    /// server supports them.
+    pub unsafe fn blit_scaled(
+        &self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_handle(handle)?;
+        dispatch!(self, p => p.blit_scaled(src, dst, filter, handle))
+    }
+
+    pub unsafe fn blit_slice(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_handle(handle)?;
+        dispatch!(self, p => p.blit_slice(data, layout, size, handle))
+    }
+
+    /// Presents part of a staging image through the buffer's own surface on Wayland, where
+    /// surfaces take on the size of the buffer attached to them. X11 windows can have staging
+    /// buffers blitted onto them on their own.
+    pub unsafe fn blit_staged(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_handle(handle)?;
+        match self {
+            PixelBuffer::X11(_) => Ok(false),
+            PixelBuffer::Wayland(p) => p.blit_staged(data, layout, src_pos, dst_pos, size, handle),
+        }
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        dispatch!(self, p => p.bits_per_pixel())
+    }
+
+    pub fn width(&self) -> u32 {
+        dispatch!(self, p => p.width())
+    }
+
+    pub fn row_len(&self) -> usize {
+        dispatch!(self, p => p.row_len())
+    }
+
+    pub fn height(&self) -> u32 {
+        dispatch!(self, p => p.height())
+    }
+
+    pub fn uses_shm(&self) -> bool {
+        match self {
+            PixelBuffer::X11(p) => p.uses_shm(),
+            PixelBuffer::Wayland(_) => false,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        dispatch!(self, p => p.bytes())
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        dispatch!(self, p => p.bytes_mut())
+    }
+}
@@ -0,0 +1,729 @@
+use crate::{
+    damage::Damage,
+    memory::{copy_rect, Layout},
+    rect::{BlitArea, Rect},
+    scale::ScaleFilter,
+    BlitError, PixelBufferFormatType,
+};
+use raw_window_handle::{unix::WaylandHandle, RawWindowHandle};
+use std::{
+    cell::RefCell,
+    convert::TryInto,
+    io, mem,
+    os::unix::io::RawFd,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use wayland_client::{
+    protocol::{
+        wl_buffer::{self, WlBuffer},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Display, EventQueue, GlobalManager, Proxy,
+};
+use wayland_protocols::viewporter::client::wp_viewporter::WpViewporter;
+
+pub struct PixelBuffer {
+    surface: WlSurface,
+    display: Display,
+    event_queue: RefCell<EventQueue>,
+    shm: WlShm,
+    /// The compositor's `wp_viewporter`, if it supports scaling surfaces.
+    viewporter: Option<WpViewporter>,
+    /// Whether the compositor supports the alpha channel in `pixel_format`.
+    alpha_supported: bool,
+    pixel_format: PixelBufferFormatType,
+    format: wl_shm::Format,
+    layout: Layout,
+    /// The size `data` was allocated for, which may be larger than `layout`.
+    capacity: (u32, u32),
+    /// The pixel data, which the compositor never sees. Blits copy it into `surface_buffers`.
+    data: Vec<u8>,
+    /// The buffers holding the surface's contents.
+    ///
+    /// Wayland surfaces always display entire buffers, and the compositor may keep reading an
+    /// attached buffer until it releases it. Blits copy just the blitted rectangles into a buffer
+    /// that isn't in use, and attach that one.
+    surface_buffers: RefCell<SurfaceBuffers>,
+}
+
+/// The most buffers kept for presenting. Blits wait for the compositor to release one of them if
+/// they're all in use.
+const MAX_SURFACE_BUFFERS: usize = 3;
+
+#[derive(Default)]
+struct SurfaceBuffers {
+    buffers: Vec<ShmBuffer>,
+    /// The index of the buffer attached last, which holds the surface's current contents.
+    front: Option<usize>,
+}
+
+unsafe impl Send for PixelBuffer {}
+
+fn px_cast(u: u32) -> Result<i32, BlitError> {
+    u.try_into().map_err(|_| BlitError::DimensionsTooLarge)
+}
+
+/// The width, height, stride and length of `layout`, which `wl_shm` takes as `i32`s.
+fn shm_geometry(layout: Layout) -> Result<(i32, i32, i32, i32), BlitError> {
+    let byte_cast = |n: usize| n.try_into().map_err(|_| BlitError::DimensionsTooLarge);
+    Ok((
+        px_cast(layout.width)?,
+        px_cast(layout.height)?,
+        byte_cast(layout.stride)?,
+        byte_cast(layout.len())?,
+    ))
+}
+
+/// Returns the `wl_shm` format with the same memory layout as `format`, with the alpha channel
+/// either used or ignored.
+///
+/// `wl_shm` formats are named after the layout of a little-endian integer, so the order of the
+/// channels is reversed compared to ours.
+fn shm_format(format: PixelBufferFormatType, alpha: bool) -> wl_shm::Format {
+    match (format, alpha) {
+        (PixelBufferFormatType::BGRA, false) => wl_shm::Format::Xrgb8888,
+        (PixelBufferFormatType::BGRA, true) => wl_shm::Format::Argb8888,
+        (PixelBufferFormatType::RGBA, false) => wl_shm::Format::Xbgr8888,
+        (PixelBufferFormatType::RGBA, true) => wl_shm::Format::Abgr8888,
+        (PixelBufferFormatType::BGR, _) => wl_shm::Format::Rgb888,
+        (PixelBufferFormatType::RGB, _) => wl_shm::Format::Bgr888,
+    }
+}
+
+/// Whether the compositor supports `format`, given the formats it advertised.
+fn is_supported(formats: &[wl_shm::Format], format: wl_shm::Format) -> bool {
+    // Every compositor must support these two, but won't necessarily advertise them.
+    let required = [wl_shm::Format::Xrgb8888, wl_shm::Format::Argb8888];
+    required.contains(&format) || formats.contains(&format)
+}
+
+fn surface(handle: RawWindowHandle) -> Option<*mut std::ffi::c_void> {
+    match handle {
+        RawWindowHandle::Wayland(WaylandHandle { surface, .. }) => Some(surface),
+        _ => None,
+    }
+}
+
+/// Creates an anonymous file suitable for sharing with the compositor.
+fn create_shm_fd() -> io::Result<RawFd> {
+    #[cfg(target_os = "linux")]
+    let fd = unsafe { libc::memfd_create(b"winit-blit\0".as_ptr() as _, libc::MFD_CLOEXEC) };
+
+    #[cfg(not(target_os = "linux"))]
+    let fd = unsafe {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "/winit-blit-{}-{}\0",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let fd = libc::shm_open(
+            name.as_ptr() as _,
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC,
+            0o600,
+        );
+        if fd != -1 {
+            libc::shm_unlink(name.as_ptr() as _);
+        }
+        fd
+    };
+
+    if fd == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(fd)
+    }
+}
+
+/// Creates a `wl_buffer` that clears `busy` once the compositor releases it.
+fn create_buffer(
+    pool: &WlShmPool,
+    (width, height, stride): (i32, i32, i32),
+    format: wl_shm::Format,
+    busy: Arc<AtomicBool>,
+) -> io::Result<WlBuffer> {
+    pool.create_buffer(0, width, height, stride, format, |buffer| {
+        buffer.implement_closure_threadsafe(
+            move |event, _| {
+                if let wl_buffer::Event::Release = event {
+                    busy.store(false, Ordering::Release);
+                }
+            },
+            (),
+        )
+    })
+    .map_err(|()| io::Error::other("wl_shm_pool is no longer alive"))
+}
+
+/// A `wl_buffer` backed by its own memory-mapped `wl_shm_pool`.
+struct ShmBuffer {
+    pool: WlShmPool,
+    buffer: WlBuffer,
+    data: *mut u8,
+    len: usize,
+    /// Whether the buffer is attached and not yet released, in which case the compositor may
+    /// still be reading it and it must not be written to.
+    busy: Arc<AtomicBool>,
+    /// The parts of the surface that changed since the buffer was last attached.
+    stale: Damage,
+}
+
+impl ShmBuffer {
+    fn new(shm: &WlShm, layout: Layout, format: wl_shm::Format) -> Result<ShmBuffer, BlitError> {
+        let (width, height, stride, pool_len) = shm_geometry(layout)?;
+        let len = layout.len();
+        let fd = create_shm_fd().map_err(BlitError::AllocationFailed)?;
+        let data = unsafe {
+            if libc::ftruncate(fd, len as libc::off_t) == -1 {
+                let error = io::Error::last_os_error();
+                libc::close(fd);
+                return Err(BlitError::AllocationFailed(error));
+            }
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            )
+        };
+        if data == libc::MAP_FAILED {
+            let error = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(BlitError::AllocationFailed(error));
+        }
+
+        // The fd gets duplicated when the request is sent, so we can close ours right away.
+        let pool = shm.create_pool(fd, pool_len, |pool| pool.implement_dummy());
+        unsafe { libc::close(fd) };
+        let pool = pool.map_err(|()| io::Error::other("wl_shm is no longer alive"))?;
+        let busy = Arc::new(AtomicBool::new(false));
+        let buffer = create_buffer(&pool, (width, height, stride), format, busy.clone())?;
+
+        Ok(ShmBuffer {
+            pool,
+            buffer,
+            data: data as *mut u8,
+            len,
+            busy,
+            stale: Damage::default(),
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data, self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data, self.len) }
+    }
+}
+
+impl Drop for ShmBuffer {
+    fn drop(&mut self) {
+        self.buffer.destroy();
+        self.pool.destroy();
+        unsafe {
+            libc::munmap(self.data as *mut _, self.len);
+        }
+    }
+}
+
+impl PixelBuffer {
+    pub unsafe fn new(
+        width: u32,
+        height: u32,
+        format: PixelBufferFormatType,
+        raw_window_handle: RawWindowHandle,
+    ) -> Result<PixelBuffer, BlitError> {
+        let (display, surface) = match raw_window_handle {
+            RawWindowHandle::Wayland(WaylandHandle {
+                display, surface, ..
+            }) => (display, surface),
+            _ => return Err(BlitError::UnsupportedHandle),
+        };
+
+        // Use our own event queue, so that we don't interfere with whoever owns the connection.
+        let (display, mut event_queue) = Display::from_external_display(display as _);
+        let surface: WlSurface = Proxy::<WlSurface>::from_c_ptr(surface as _).into();
+
+        let globals = GlobalManager::new(&display);
+        event_queue.sync_roundtrip()?;
+        let formats = Arc::new(Mutex::new(Vec::new()));
+        let shm: WlShm = {
+            let formats = formats.clone();
+            globals
+                .instantiate_range(1, 1, move |shm| {
+                    shm.implement_closure_threadsafe(
+                        move |event, _| {
+                            if let wl_shm::Event::Format { format } = event {
+                                formats.lock().unwrap().push(format);
+                            }
+                        },
+                        (),
+                    )
+                })
+                .map_err(|_| io::Error::other("Compositor doesn't support wl_shm"))?
+        };
+        let viewporter = globals
+            .instantiate_range(1, 1, |viewporter| viewporter.implement_dummy())
+            .ok();
+        event_queue.sync_roundtrip()?;
+
+        let formats = formats.lock().unwrap().clone();
+        let alpha_supported = is_supported(&formats, shm_format(format, true));
+        let shm_format = shm_format(format, false);
+        if !is_supported(&formats, shm_format) {
+            return Err(BlitError::FormatNotSupported);
+        }
+
+        let layout = Layout::new(width, height, format);
+        shm_geometry(layout)?;
+        Ok(PixelBuffer {
+            surface,
+            display,
+            event_queue: RefCell::new(event_queue),
+            shm,
+            viewporter,
+            alpha_supported,
+            pixel_format: format,
+            format: shm_format,
+            layout,
+            capacity: (width, height),
+            data: vec![0; layout.len()],
+            surface_buffers: RefCell::new(SurfaceBuffers::default()),
+        })
+    }
+
+    pub fn capacity(&self) -> (u32, u32) {
+        self.capacity
+    }
+
+    /// Changes the buffer's size, reusing its memory. The size must fit in `capacity`.
+    ///
+    /// The surface takes on the new size, blank until it gets blitted to.
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.layout.width = width;
+        self.layout.height = height;
+        *self.surface_buffers.get_mut() = SurfaceBuffers::default();
+    }
+
+    /// Switches the buffer to a format that either uses or ignores the alpha channel. Wayland
+    /// expects it to be premultiplied.
+    pub fn set_alpha(&mut self, alpha: bool) -> Result<(), BlitError> {
+        if alpha && !self.alpha_supported {
+            return Err(BlitError::FormatNotSupported);
+        }
+        self.format = shm_format(self.pixel_format, alpha);
+        *self.surface_buffers.get_mut() = SurfaceBuffers::default();
+        Ok(())
+    }
+
+    /// Replaces the buffer's memory with zeroed memory of the given size.
+    pub fn reallocate(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        let layout = self.layout.resized(width, height);
+        shm_geometry(layout)?;
+        self.layout = layout;
+        self.data = vec![0; layout.len()];
+        self.capacity = (width, height);
+        *self.surface_buffers.get_mut() = SurfaceBuffers::default();
+        Ok(())
+    }
+
+    fn check_surface(&self, handle: RawWindowHandle) -> Result<(), BlitError> {
+        match surface(handle) {
+            Some(surface) if surface == self.surface.as_ref().c_ptr() as _ => Ok(()),
+            Some(_) => Err(BlitError::WindowMismatch),
+            None => Err(BlitError::UnsupportedHandle),
+        }
+    }
+
+    /// Wayland surfaces take on the size of the buffer attached to them, so that's what blits get
+    /// clipped to.
+    pub unsafe fn window_size(&self, handle: RawWindowHandle) -> Result<(u32, u32), BlitError> {
+        self.check_surface(handle)?;
+        Ok((self.layout.width, self.layout.height))
+    }
+
+    pub unsafe fn blit_rect(
+        &self,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        blit_size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<(), BlitError> {
+        self.blit_rects(&[(src_pos, dst_pos, blit_size)], handle)
+    }
+
+    /// Presents several rectangles in a single commit.
+    pub unsafe fn blit_rects(
+        &self,
+        areas: &[BlitArea],
+        handle: RawWindowHandle,
+    ) -> Result<(), BlitError> {
+        self.check_surface(handle)?;
+        self.present(&self.data, self.layout, areas, None)
+    }
+
+    /// Presents `src` scaled to fill the whole surface through `wp_viewporter`.
+    ///
+    /// Compositors pick their own filtering, which is usually bilinear, and a viewport can only
+    /// scale the surface as a whole. Blits with `ScaleFilter::Nearest`, with a `dst` other than
+    /// the whole surface, or on compositors without `wp_viewporter` need to be scaled in
+    /// software.
+    pub unsafe fn blit_scaled(
+        &self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_surface(handle)?;
+        if self.viewporter.is_none()
+            || filter != ScaleFilter::Bilinear
+            || dst != Rect::from_size(self.width(), self.height())
+        {
+            return Ok(false);
+        }
+        let size = (px_cast(dst.width)?, px_cast(dst.height)?);
+        let pos = (src.x as u32, src.y as u32);
+        let area = (pos, pos, (src.width, src.height));
+        self.present(&self.data, self.layout, &[area], Some((src, size)))?;
+        Ok(true)
+    }
+
+    /// Presents `size` pixels of the image in `data`, which has the buffer's format, at
+    /// `dst_pos`.
+    ///
+    /// The surface takes on the size of whatever buffer is attached to it, so a staging buffer of
+    /// another size can't be presented on its own. Its pixels get copied into one of this buffer's
+    /// surface buffers instead, along with everything presented before.
+    pub unsafe fn blit_staged(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_surface(handle)?;
+        self.present(data, layout, &[(src_pos, dst_pos, size)], None)?;
+        Ok(true)
+    }
+
+    /// Copies each of the `areas` of the image in `data` into a buffer the compositor isn't
+    /// reading, then attaches that buffer and commits it. If `scale` is given, the surface shows
+    /// only its rectangle of the buffer, stretched to its size.
+    fn present(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        areas: &[BlitArea],
+        scale: Option<(Rect, (i32, i32))>,
+    ) -> Result<(), BlitError> {
+        // Empty buffers never get attached, and `wl_shm` doesn't allow creating them anyway.
+        if self.layout.width == 0 || self.layout.height == 0 {
+            return Ok(());
+        }
+        let damage = areas
+            .iter()
+            .map(|&(_, pos, size)| {
+                Ok((
+                    px_cast(pos.0)?,
+                    px_cast(pos.1)?,
+                    px_cast(size.0)?,
+                    px_cast(size.1)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, BlitError>>()?;
+
+        let mut surface = self.surface_buffers.borrow_mut();
+        let index = self.free_buffer(&mut surface)?;
+        let SurfaceBuffers { buffers, front } = &mut *surface;
+
+        // Catch up on the changes presented through the other buffers since this one was last
+        // attached.
+        let stale = mem::take(&mut buffers[index].stale);
+        if let Some(front) = front.filter(|&front| front != index) {
+            let (buffer, front) = match index < front {
+                true => {
+                    let (a, b) = buffers.split_at_mut(front);
+                    (&mut a[index], &b[0])
+                }
+                false => {
+                    let (a, b) = buffers.split_at_mut(index);
+                    (&mut b[0], &a[front])
+                }
+            };
+            for rect in stale.rects() {
+                let pos = (rect.x as u32, rect.y as u32);
+                let size = (rect.width, rect.height);
+                copy_rect(
+                    front.as_slice(),
+                    self.layout,
+                    pos,
+                    buffer.as_mut_slice(),
+                    self.layout,
+                    pos,
+                    size,
+                );
+            }
+        }
+
+        for &(src_pos, dst_pos, size) in areas {
+            copy_rect(
+                data,
+                layout,
+                src_pos,
+                buffers[index].as_mut_slice(),
+                self.layout,
+                dst_pos,
+                size,
+            );
+        }
+        for (i, buffer) in buffers.iter_mut().enumerate() {
+            if i != index {
+                for &(x, y, w, h) in &damage {
+                    buffer.stale.add(Rect::new(x, y, w as u32, h as u32));
+                }
+            }
+        }
+        *front = Some(index);
+
+        let buffer = &buffers[index];
+        buffer.busy.store(true, Ordering::Release);
+        self.surface.attach(Some(&buffer.buffer), 0, 0);
+        let viewport = match (scale, &self.viewporter) {
+            (Some((src, (width, height))), Some(viewporter)) => {
+                let viewport = viewporter
+                    .get_viewport(&self.surface, |viewport| viewport.implement_dummy())
+                    .map_err(|()| io::Error::other("wp_viewporter is no longer alive"))?;
+                viewport.set_source(
+                    f64::from(src.x),
+                    f64::from(src.y),
+                    f64::from(src.width),
+                    f64::from(src.height),
+                );
+                viewport.set_destination(width, height);
+                self.surface.damage(0, 0, width, height);
+                Some(viewport)
+            }
+            _ => {
+                for (x, y, w, h) in damage {
+                    if self.surface.as_ref().version() >= 4 {
+                        self.surface.damage_buffer(x, y, w, h);
+                    } else {
+                        self.surface.damage(x, y, w, h);
+                    }
+                }
+                None
+            }
+        };
+        self.surface.commit();
+        // Each surface can only have a single viewport, which other pixel buffers may need. The
+        // scaling stays in effect until the next commit.
+        if let Some(viewport) = viewport {
+            viewport.destroy();
+        }
+        self.display.flush()?;
+
+        // Release events only arrive through dispatching, and other events pile up otherwise.
+        self.event_queue.borrow_mut().dispatch_pending()?;
+
+        Ok(())
+    }
+
+    /// Finds a buffer the compositor isn't reading, allocating one if there are none, or waiting
+    /// for one to be released if there are already `MAX_SURFACE_BUFFERS`.
+    fn free_buffer(&self, surface: &mut SurfaceBuffers) -> Result<usize, BlitError> {
+        let mut event_queue = self.event_queue.borrow_mut();
+        event_queue.dispatch_pending()?;
+        loop {
+            let free = surface
+                .buffers
+                .iter()
+                .position(|buffer| !buffer.busy.load(Ordering::Acquire));
+            if let Some(index) = free {
+                return Ok(index);
+            }
+            if surface.buffers.len() < MAX_SURFACE_BUFFERS {
+                let mut buffer = ShmBuffer::new(&self.shm, self.layout, self.format)?;
+                // A new buffer is missing everything presented so far.
+                if surface.front.is_some() {
+                    buffer
+                        .stale
+                        .add(Rect::from_size(self.layout.width, self.layout.height));
+                }
+                surface.buffers.push(buffer);
+                return Ok(surface.buffers.len() - 1);
+            }
+            self.display.flush()?;
+            event_queue.dispatch()?;
+        }
+    }
+
+    /// The caller's memory isn't shared with the compositor, so the image gets copied into one of
+    /// the surface buffers.
+    pub unsafe fn blit_slice(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.blit_staged(data, layout, (0, 0), (0, 0), size, handle)
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.layout.bytes_per_pixel * 8
+    }
+
+    pub fn width(&self) -> u32 {
+        self.layout.width
+    }
+
+    pub fn row_len(&self) -> usize {
+        self.layout.stride
+    }
+
+    pub fn height(&self) -> u32 {
+        self.layout.height
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data[..self.layout.len()]
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.layout.len();
+        &mut self.data[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayland_client::protocol::wl_compositor::WlCompositor;
+
+    /// A surface on the compositor named by `WAYLAND_DISPLAY`, or `None` if there isn't one.
+    ///
+    /// The tests using it are ignored by default, since they need a compositor. Run them with
+    /// `cargo test -- --ignored`, inside a headless compositor such as
+    /// `weston --backend=headless-backend.so` on a machine without a display.
+    struct TestSurface {
+        display: Display,
+        _event_queue: EventQueue,
+        surface: WlSurface,
+    }
+
+    impl TestSurface {
+        fn open() -> Option<TestSurface> {
+            let (display, mut event_queue) = Display::connect_to_env().ok()?;
+            let globals = GlobalManager::new(&display);
+            event_queue.sync_roundtrip().ok()?;
+            let compositor: WlCompositor = globals
+                .instantiate_range(1, 4, |compositor| compositor.implement_dummy())
+                .ok()?;
+            let surface = compositor
+                .create_surface(|surface| surface.implement_dummy())
+                .ok()?;
+            Some(TestSurface {
+                display,
+                _event_queue: event_queue,
+                surface,
+            })
+        }
+
+        fn handle(&self) -> RawWindowHandle {
+            RawWindowHandle::Wayland(WaylandHandle {
+                surface: self.surface.as_ref().c_ptr() as _,
+                display: self.display.get_display_ptr() as _,
+                ..WaylandHandle::empty()
+            })
+        }
+    }
+
+    #[test]
+    fn shm_geometry_fits_in_i32() {
+        let layout = Layout::new(100, 50, PixelBufferFormatType::BGRA);
+        assert_eq!((100, 50, 400, 20000), shm_geometry(layout).unwrap());
+        // The width fits in an `i32`, but neither the stride nor the pool's size does.
+        let wide = Layout::new(i32::MAX as u32, 1, PixelBufferFormatType::BGRA);
+        assert!(matches!(
+            shm_geometry(wide),
+            Err(BlitError::DimensionsTooLarge)
+        ));
+        let tall = Layout::new(1 << 16, 1 << 14, PixelBufferFormatType::BGRA);
+        assert!(matches!(
+            shm_geometry(tall),
+            Err(BlitError::DimensionsTooLarge)
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a Wayland compositor"]
+    fn pixelbuffer_blit() {
+        let surface = TestSurface::open().expect("no Wayland compositor available");
+        unsafe {
+            let mut pb =
+                PixelBuffer::new(31, 17, PixelBufferFormatType::BGRA, surface.handle()).unwrap();
+            assert_eq!(31 * 4, pb.row_len());
+            assert_eq!(31 * 4 * 17, pb.bytes().len());
+            for byte in pb.bytes_mut() {
+                *byte = 0x7f;
+            }
+            pb.blit_rect((0, 0), (0, 0), (31, 17), surface.handle())
+                .unwrap();
+
+            // Only the blitted rectangle reaches the surface.
+            for byte in pb.bytes_mut() {
+                *byte = 0x11;
+            }
+            pb.blit_rect((1, 2), (3, 4), (5, 6), surface.handle())
+                .unwrap();
+            let surface_buffers = pb.surface_buffers.borrow();
+            let front = &surface_buffers.buffers[surface_buffers.front.unwrap()];
+            let pixel = |x: usize, y: usize| front.as_slice()[y * pb.row_len() + x * 4];
+            assert_eq!(0x11, pixel(3, 4));
+            assert_eq!(0x11, pixel(7, 9));
+            assert_eq!(0x7f, pixel(8, 9));
+            assert_eq!(0x7f, pixel(0, 0));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Wayland compositor"]
+    fn pixelbuffer_blit_staged() {
+        let surface = TestSurface::open().expect("no Wayland compositor available");
+        unsafe {
+            let mut pb =
+                PixelBuffer::new(8, 8, PixelBufferFormatType::BGRA, surface.handle()).unwrap();
+            pb.bytes_mut().fill(0x7f);
+            pb.blit_rect((0, 0), (0, 0), (8, 8), surface.handle())
+                .unwrap();
+
+            // A smaller staging image lands inside the surface, which keeps its size.
+            let layout = Layout::new(3, 3, PixelBufferFormatType::BGRA);
+            let staging = vec![0x11; layout.len()];
+            let staged = pb.blit_staged(&staging, layout, (1, 1), (5, 6), (2, 2), surface.handle());
+            assert!(staged.unwrap());
+            let surface_buffers = pb.surface_buffers.borrow();
+            let front = &surface_buffers.buffers[surface_buffers.front.unwrap()];
+            assert_eq!(pb.bytes().len(), front.as_slice().len());
+            let pixel = |x: usize, y: usize| front.as_slice()[y * pb.row_len() + x * 4];
+            assert_eq!(0x11, pixel(5, 6));
+            assert_eq!(0x11, pixel(6, 7));
+            assert_eq!(0x7f, pixel(4, 6));
+            assert_eq!(0x7f, pixel(0, 0));
+        }
+    }
+}
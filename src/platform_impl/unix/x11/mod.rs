@@ -0,0 +1,656 @@
+use crate::{memory::Layout, rect::Rect, scale::ScaleFilter, BlitError, PixelBufferFormatType};
+use raw_window_handle::{
+    unix::{XcbHandle, XlibHandle},
+    RawWindowHandle,
+};
+use std::{convert::TryInto, io, os::raw::c_int, ptr, sync::OnceLock};
+use x11_dl::xlib::{self, Display, Drawable, XImage, Xlib, GC};
+
+mod render;
+mod shm;
+
+/// The memory backing a pixel buffer's image.
+enum Storage {
+    /// Regular heap memory, sent to the server over the X connection on every blit.
+    Heap(Vec<u8>),
+    /// Shared memory, read directly by the server on blit.
+    Shm(shm::Segment),
+}
+
+impl Storage {
+    /// Allocates `len` bytes of storage, shared with the X server behind `display` if possible.
+    unsafe fn new(display: *mut Display, len: usize) -> Storage {
+        match shm::Segment::new(display, len) {
+            Some(segment) => Storage::Shm(segment),
+            None => Storage::Heap(vec![0; len]),
+        }
+    }
+
+    /// The `data` and `obdata` pointers for an image using this storage.
+    fn image_pointers(&mut self) -> (*mut u8, xlib::XPointer) {
+        match self {
+            Storage::Heap(data) => (data.as_mut_ptr(), ptr::null_mut()),
+            Storage::Shm(segment) => (segment.as_ptr(), segment.info_ptr() as xlib::XPointer),
+        }
+    }
+
+    /// Detaches shared memory from the X server. The storage must not be used afterwards.
+    unsafe fn release(&mut self, display: *mut Display) {
+        if let Storage::Shm(segment) = self {
+            segment.release(display);
+        }
+    }
+}
+
+pub struct PixelBuffer {
+    display: *mut Display,
+    /// Whether `display` is a private connection that must be closed when the buffer is dropped.
+    ///
+    /// Xcb handles don't carry an Xlib `Display`, so we open our own connection to the default
+    /// display and draw onto the window through that. Window IDs are global to the X server, so
+    /// this works regardless of which connection created the window.
+    owns_display: bool,
+    window: xlib::Window,
+    /// The window's visual, needed to composite onto it with RENDER.
+    visual: *mut xlib::Visual,
+    gc: GC,
+    image: XImage,
+    storage: Storage,
+    /// The size `storage` was allocated for, which may be larger than `image`.
+    capacity: (u32, u32),
+}
+
+unsafe impl Send for PixelBuffer {}
+
+fn px_cast(u: u32) -> Result<i32, BlitError> {
+    u.try_into().map_err(|_| BlitError::DimensionsTooLarge)
+}
+
+/// Lazily loads `libX11`, returning `None` if it isn't available.
+fn load_xlib() -> Option<&'static Xlib> {
+    static XLIB: OnceLock<Option<Xlib>> = OnceLock::new();
+    XLIB.get_or_init(|| Xlib::open().ok()).as_ref()
+}
+
+/// Returns `libX11`, which must have already been loaded by `load_xlib`.
+fn xlib() -> &'static Xlib {
+    load_xlib().expect("libX11 used before being loaded")
+}
+
+/// Returns the X window ID and the Xlib display to draw onto it with.
+///
+/// The returned `bool` is `true` if the display was opened by this function and must be closed by
+/// the caller.
+unsafe fn connect(
+    handle: RawWindowHandle,
+) -> Result<(xlib::Window, *mut Display, bool), BlitError> {
+    match handle {
+        RawWindowHandle::Xlib(XlibHandle {
+            window, display, ..
+        }) => Ok((window, display as _, false)),
+        RawWindowHandle::Xcb(XcbHandle { window, .. }) => {
+            let display = (xlib().XOpenDisplay)(ptr::null());
+            if display.is_null() {
+                return Err(io::Error::other("Failed to open X display").into());
+            }
+            Ok((window as _, display, true))
+        }
+        _ => Err(BlitError::UnsupportedHandle),
+    }
+}
+
+/// Returns the X window ID referenced by `handle`, without opening any connections.
+fn window_id(handle: RawWindowHandle) -> Option<xlib::Window> {
+    match handle {
+        RawWindowHandle::Xlib(XlibHandle { window, .. }) => Some(window),
+        RawWindowHandle::Xcb(XcbHandle { window, .. }) => Some(window as _),
+        _ => None,
+    }
+}
+
+/// Determines the byte-level layout of a 24 or 32 bit `ZPixmap` image for the given visual, if
+/// it corresponds to one of our pixel formats.
+///
+/// Images are always created with `LSBFirst` byte order, so a visual with a red mask of
+/// `0xff0000` ends up laid out in memory as blue-green-red(-padding).
+fn visual_formats(visual: &xlib::Visual) -> Option<(PixelBufferFormatType, PixelBufferFormatType)> {
+    match (visual.red_mask, visual.green_mask, visual.blue_mask) {
+        (0xff0000, 0x00ff00, 0x0000ff) => {
+            Some((PixelBufferFormatType::BGR, PixelBufferFormatType::BGRA))
+        }
+        (0x0000ff, 0x00ff00, 0xff0000) => {
+            Some((PixelBufferFormatType::RGB, PixelBufferFormatType::RGBA))
+        }
+        _ => None,
+    }
+}
+
+impl PixelBuffer {
+    pub unsafe fn new(
+        width: u32,
+        height: u32,
+        format: PixelBufferFormatType,
+        raw_window_handle: RawWindowHandle,
+    ) -> Result<PixelBuffer, BlitError> {
+        let xlib = load_xlib().ok_or_else(|| io::Error::other("Failed to load libX11"))?;
+        let (window, display, owns_display) = connect(raw_window_handle)?;
+        let close = |display| {
+            if owns_display {
+                (xlib.XCloseDisplay)(display);
+            }
+        };
+
+        let mut attributes: xlib::XWindowAttributes = std::mem::zeroed();
+        let status = (xlib.XGetWindowAttributes)(display, window, &mut attributes);
+        if status == 0 {
+            close(display);
+            return Err(io::Error::other("Failed to query X window attributes").into());
+        }
+        let visual = &*attributes.visual;
+
+        let bits_per_pixel = match visual_formats(visual) {
+            Some((packed, _)) if visual.class == xlib::TrueColor && format == packed => 24,
+            Some((_, padded)) if visual.class == xlib::TrueColor && format == padded => 32,
+            _ => {
+                close(display);
+                return Err(BlitError::FormatNotSupported);
+            }
+        };
+        let bytes_per_line = (width as usize * bits_per_pixel / 8 + 3) & !3;
+
+        let len = bytes_per_line * height as usize;
+        let mut storage = Storage::new(display, len);
+        let (data, obdata) = storage.image_pointers();
+        let mut image = XImage {
+            width: width as c_int,
+            height: height as c_int,
+            xoffset: 0,
+            format: xlib::ZPixmap,
+            data: data as _,
+            byte_order: xlib::LSBFirst,
+            bitmap_unit: 32,
+            bitmap_bit_order: xlib::LSBFirst,
+            bitmap_pad: 32,
+            depth: attributes.depth,
+            bytes_per_line: bytes_per_line as c_int,
+            bits_per_pixel: bits_per_pixel as c_int,
+            red_mask: visual.red_mask,
+            green_mask: visual.green_mask,
+            blue_mask: visual.blue_mask,
+            obdata,
+            funcs: std::mem::zeroed(),
+        };
+        if (xlib.XInitImage)(&mut image) == 0 {
+            storage.release(display);
+            close(display);
+            return Err(BlitError::FormatNotSupported);
+        }
+
+        let gc = (xlib.XCreateGC)(display, window, 0, ptr::null_mut());
+        if gc.is_null() {
+            storage.release(display);
+            close(display);
+            return Err(io::Error::other("Failed to create X graphics context").into());
+        }
+
+        Ok(PixelBuffer {
+            display,
+            owns_display,
+            window,
+            visual: attributes.visual,
+            gc,
+            image,
+            storage,
+            capacity: (width, height),
+        })
+    }
+
+    pub fn capacity(&self) -> (u32, u32) {
+        self.capacity
+    }
+
+    /// Changes the image's size without touching its memory. The size must fit in `capacity`.
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.image.width = width as c_int;
+        self.image.height = height as c_int;
+    }
+
+    /// Replaces the image's memory with zeroed memory of the given size.
+    pub unsafe fn reallocate(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        let bytes_per_line = (width as usize * self.bits_per_pixel() / 8 + 3) & !3;
+        let mut storage = Storage::new(self.display, bytes_per_line * height as usize);
+        let (data, obdata) = storage.image_pointers();
+        let mut image = XImage {
+            width: width as c_int,
+            height: height as c_int,
+            data: data as _,
+            bytes_per_line: bytes_per_line as c_int,
+            obdata,
+            ..self.image
+        };
+        if (xlib().XInitImage)(&mut image) == 0 {
+            storage.release(self.display);
+            return Err(BlitError::FormatNotSupported);
+        }
+
+        self.storage.release(self.display);
+        self.storage = storage;
+        self.image = image;
+        self.capacity = (width, height);
+        Ok(())
+    }
+
+    /// X11 always presents the alpha channel of windows with a 32-bit ARGB visual, which
+    /// compositors treat as premultiplied, and never that of other windows.
+    pub fn set_alpha(&mut self, alpha: bool) -> Result<(), BlitError> {
+        match alpha && (self.image.depth != 32 || self.image.bits_per_pixel != 32) {
+            true => Err(BlitError::FormatNotSupported),
+            false => Ok(()),
+        }
+    }
+
+    fn check_window(&self, handle: RawWindowHandle) -> Result<(), BlitError> {
+        match window_id(handle) {
+            Some(window) if window == self.window => Ok(()),
+            Some(_) => Err(BlitError::WindowMismatch),
+            None => Err(BlitError::UnsupportedHandle),
+        }
+    }
+
+    pub unsafe fn window_size(&self, handle: RawWindowHandle) -> Result<(u32, u32), BlitError> {
+        self.check_window(handle)?;
+        let mut root = 0;
+        let (mut x, mut y) = (0, 0);
+        let (mut width, mut height, mut border, mut depth) = (0, 0, 0, 0);
+        let status = (xlib().XGetGeometry)(
+            self.display,
+            self.window,
+            &mut root,
+            &mut x,
+            &mut y,
+            &mut width,
+            &mut height,
+            &mut border,
+            &mut depth,
+        );
+        if status == 0 {
+            return Err(io::Error::other("Failed to query X window geometry").into());
+        }
+        Ok((width, height))
+    }
+
+    pub unsafe fn blit_rect(
+        &self,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        blit_size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<(), BlitError> {
+        self.check_window(handle)?;
+        if self.bytes().is_empty() {
+            return Ok(());
+        }
+        let (src_x, src_y) = (px_cast(src_pos.0)?, px_cast(src_pos.1)?);
+        let (dst_x, dst_y) = (px_cast(dst_pos.0)?, px_cast(dst_pos.1)?);
+        self.put_image(self.window, (src_x, src_y), (dst_x, dst_y), blit_size);
+        (xlib().XFlush)(self.display);
+        Ok(())
+    }
+
+    /// Scales through the RENDER extension, by uploading `src` into a pixmap and compositing it
+    /// onto the window with a scaling transform. Returns `false` if the X server doesn't support
+    /// RENDER, or if the rectangles don't fit in the protocol's 16-bit coordinates.
+    pub unsafe fn blit_scaled(
+        &self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_window(handle)?;
+        let fits = |size: u32| size <= i16::MAX as u32;
+        let in_range = |pos: i32| (i16::MIN as i32..=i16::MAX as i32).contains(&pos);
+        if ![src.width, src.height, dst.width, dst.height]
+            .iter()
+            .all(|&size| fits(size))
+            || !in_range(dst.x)
+            || !in_range(dst.y)
+        {
+            return Ok(false);
+        }
+        let format = match render::visual_format(self.display, self.visual) {
+            Some(format) => format,
+            None => return Ok(false),
+        };
+
+        let xlib = xlib();
+        let pixmap = (xlib.XCreatePixmap)(
+            self.display,
+            self.window,
+            src.width,
+            src.height,
+            self.image.depth as _,
+        );
+        self.put_image(pixmap, (src.x, src.y), (0, 0), (src.width, src.height));
+        render::composite_scaled(
+            self.display,
+            format,
+            pixmap,
+            (src.width, src.height),
+            self.window,
+            dst,
+            filter,
+        );
+        (xlib.XFreePixmap)(self.display, pixmap);
+        (xlib.XFlush)(self.display);
+        Ok(true)
+    }
+
+    /// Draws `size` pixels of the image from `src` onto `drawable` at `dst`, without flushing.
+    unsafe fn put_image(
+        &self,
+        drawable: Drawable,
+        (src_x, src_y): (i32, i32),
+        (dst_x, dst_y): (i32, i32),
+        size: (u32, u32),
+    ) {
+        let xlib = xlib();
+        // Neither `XPutImage` nor `XShmPutImage` write through the image, but they take it by
+        // mutable pointer anyway.
+        let image = &self.image as *const XImage as *mut XImage;
+        match (&self.storage, shm::xext()) {
+            (Storage::Shm(_), Some(xext)) => {
+                (xext.XShmPutImage)(
+                    self.display,
+                    drawable,
+                    self.gc,
+                    image,
+                    src_x,
+                    src_y,
+                    dst_x,
+                    dst_y,
+                    size.0,
+                    size.1,
+                    xlib::False,
+                );
+                // The server reads the pixels straight out of the segment, so wait until it's
+                // done before handing the buffer back to the caller for drawing.
+                (xlib.XSync)(self.display, xlib::False);
+            }
+            _ => {
+                (xlib.XPutImage)(
+                    self.display,
+                    drawable,
+                    self.gc,
+                    image,
+                    src_x,
+                    src_y,
+                    dst_x,
+                    dst_y,
+                    size.0,
+                    size.1,
+                );
+            }
+        }
+    }
+
+    /// Presents `size` pixels of the image in `data` straight out of the caller's memory.
+    pub unsafe fn blit_slice(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_window(handle)?;
+        let xlib = xlib();
+        let mut image = XImage {
+            width: px_cast(layout.width)?,
+            height: px_cast(layout.height)?,
+            data: data.as_ptr() as _,
+            bytes_per_line: layout
+                .stride
+                .try_into()
+                .map_err(|_| BlitError::DimensionsTooLarge)?,
+            obdata: ptr::null_mut(),
+            ..self.image
+        };
+        if (xlib.XInitImage)(&mut image) == 0 {
+            return Ok(false);
+        }
+        // `XPutImage` only reads through the image, so the data never actually gets mutated.
+        (xlib.XPutImage)(
+            self.display,
+            self.window,
+            self.gc,
+            &mut image,
+            0,
+            0,
+            0,
+            0,
+            size.0,
+            size.1,
+        );
+        (xlib.XFlush)(self.display);
+        Ok(true)
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.image.bits_per_pixel as usize
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width as u32
+    }
+
+    pub fn row_len(&self) -> usize {
+        self.image.bytes_per_line as usize
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height as u32
+    }
+
+    pub fn uses_shm(&self) -> bool {
+        match self.storage {
+            Storage::Shm(_) => true,
+            Storage::Heap(_) => false,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        let len = self.row_len() * self.height() as usize;
+        match &self.storage {
+            Storage::Heap(data) => &data[..len],
+            Storage::Shm(segment) => &segment.as_slice()[..len],
+        }
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.row_len() * self.height() as usize;
+        match &mut self.storage {
+            Storage::Heap(data) => &mut data[..len],
+            Storage::Shm(segment) => &mut segment.as_mut_slice()[..len],
+        }
+    }
+}
+
+impl Drop for PixelBuffer {
+    fn drop(&mut self) {
+        let xlib = xlib();
+        unsafe {
+            self.storage.release(self.display);
+            (xlib.XFreeGC)(self.display, self.gc);
+            if self.owns_display {
+                (xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A window on the default X display, or `None` if no X server is available.
+    ///
+    /// The tests using it are ignored by default, since they need an X server. Run them with
+    /// `cargo test -- --ignored`, under `xvfb-run` on a machine without a display.
+    struct TestWindow {
+        display: *mut Display,
+        window: xlib::Window,
+    }
+
+    impl TestWindow {
+        fn open(width: u32, height: u32) -> Option<TestWindow> {
+            let xlib = Xlib::open().ok()?;
+            unsafe {
+                let display = (xlib.XOpenDisplay)(ptr::null());
+                if display.is_null() {
+                    return None;
+                }
+                let screen = (xlib.XDefaultScreen)(display);
+                let root = (xlib.XRootWindow)(display, screen);
+                let black = (xlib.XBlackPixel)(display, screen);
+                let window =
+                    (xlib.XCreateSimpleWindow)(display, root, 0, 0, width, height, 0, black, black);
+                (xlib.XMapWindow)(display, window);
+                (xlib.XSync)(display, xlib::False);
+                Some(TestWindow { display, window })
+            }
+        }
+
+        fn handle(&self) -> RawWindowHandle {
+            RawWindowHandle::Xlib(XlibHandle {
+                window: self.window,
+                display: self.display as _,
+                ..XlibHandle::empty()
+            })
+        }
+
+        fn pixel(&self, x: i32, y: i32) -> u32 {
+            let xlib = xlib();
+            unsafe {
+                (xlib.XSync)(self.display, xlib::False);
+                let image =
+                    (xlib.XGetImage)(self.display, self.window, x, y, 1, 1, !0, xlib::ZPixmap);
+                assert_ne!(ptr::null_mut(), image);
+                let pixel = (xlib.XGetPixel)(image, 0, 0);
+                (xlib.XDestroyImage)(image);
+                pixel as u32 & 0xffffff
+            }
+        }
+    }
+
+    impl Drop for TestWindow {
+        fn drop(&mut self) {
+            let xlib = xlib();
+            unsafe {
+                (xlib.XDestroyWindow)(self.display, self.window);
+                (xlib.XCloseDisplay)(self.display);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn pixelbuffer_new_native_format() {
+        let window = TestWindow::open(16, 16).expect("no X server available");
+        unsafe {
+            let pb = PixelBuffer::new(13, 7, PixelBufferFormatType::BGRA, window.handle()).unwrap();
+            assert_eq!(32, pb.bits_per_pixel());
+            assert_eq!(13 * 4, pb.row_len());
+            assert_eq!(7 * 13 * 4, pb.bytes().len());
+
+            let rgba = PixelBuffer::new(13, 7, PixelBufferFormatType::RGBA, window.handle());
+            assert!(rgba.is_err());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn pixelbuffer_uses_shm_when_available() {
+        let window = TestWindow::open(16, 16).expect("no X server available");
+        unsafe {
+            let shm_available = shm::xext()
+                .map(|xext| (xext.XShmQueryExtension)(window.display) != 0)
+                .unwrap_or(false);
+            let pb =
+                PixelBuffer::new(16, 16, PixelBufferFormatType::BGRA, window.handle()).unwrap();
+            assert_eq!(shm_available, pb.uses_shm());
+
+            // Zero-sized buffers have nothing to share.
+            let pb = PixelBuffer::new(0, 16, PixelBufferFormatType::BGRA, window.handle()).unwrap();
+            assert!(!pb.uses_shm());
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn pixelbuffer_blit_rect() {
+        let window = TestWindow::open(16, 16).expect("no X server available");
+        unsafe {
+            let mut pb =
+                PixelBuffer::new(16, 16, PixelBufferFormatType::BGRA, window.handle()).unwrap();
+            for (y, row) in pb.bytes_mut().chunks_mut(16 * 4).enumerate() {
+                for (x, pixel) in row.chunks_mut(4).enumerate() {
+                    pixel.copy_from_slice(&[x as u8, y as u8, 0xff, 0xff]);
+                }
+            }
+            pb.blit_rect((0, 0), (0, 0), (16, 16), window.handle())
+                .unwrap();
+            assert_eq!(0xff_03_02, window.pixel(2, 3));
+
+            pb.blit_rect((4, 5), (0, 0), (1, 1), window.handle())
+                .unwrap();
+            assert_eq!(0xff_05_04, window.pixel(0, 0));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn pixelbuffer_blit_scaled() {
+        let window = TestWindow::open(16, 16).expect("no X server available");
+        unsafe {
+            let mut pb =
+                PixelBuffer::new(4, 4, PixelBufferFormatType::BGRA, window.handle()).unwrap();
+            for (i, pixel) in pb.bytes_mut().chunks_mut(4).enumerate() {
+                pixel.copy_from_slice(&[i as u8, 0, 0xff, 0xff]);
+            }
+            let scaled = pb
+                .blit_scaled(
+                    Rect::new(1, 1, 2, 2),
+                    Rect::new(0, 0, 8, 8),
+                    ScaleFilter::Nearest,
+                    window.handle(),
+                )
+                .unwrap();
+            // Every X server in use supports RENDER, including Xvfb.
+            assert!(scaled, "RENDER not available");
+            assert_eq!(0xff_00_05, window.pixel(3, 3));
+            assert_eq!(0xff_00_0a, window.pixel(4, 4));
+        }
+    }
+
+    #[test]
+    #[ignore = "needs an X server"]
+    fn pixelbuffer_blit_slice() {
+        let window = TestWindow::open(16, 16).expect("no X server available");
+        unsafe {
+            let pb = PixelBuffer::new(4, 4, PixelBufferFormatType::BGRA, window.handle()).unwrap();
+            // A 5x3 image, with rows 8 pixels apart.
+            let data: Vec<u8> = (0..8 * 3).flat_map(|i| [i as u8, 0, 0xff, 0xff]).collect();
+            let layout = Layout {
+                width: 5,
+                height: 3,
+                stride: 8 * 4,
+                bytes_per_pixel: 4,
+            };
+            assert!(pb
+                .blit_slice(&data, layout, (5, 3), window.handle())
+                .unwrap());
+            assert_eq!(0xff_00_0a, window.pixel(2, 1));
+            assert_eq!(0xff_00_14, window.pixel(4, 2));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Scaled blits through the RENDER extension's picture transforms.
+
+use crate::{rect::Rect, scale::ScaleFilter};
+use std::{
+    os::raw::{c_char, c_int},
+    ptr,
+    sync::OnceLock,
+};
+use x11_dl::{
+    xlib::{self, Display, Drawable},
+    xrender::{self, XFixed, XRenderPictFormat, XRenderPictureAttributes, XTransform, Xrender},
+};
+
+/// Lazily loads `libXrender`, returning `None` if it isn't installed.
+pub fn xrender() -> Option<&'static Xrender> {
+    static XRENDER: OnceLock<Option<Xrender>> = OnceLock::new();
+    XRENDER.get_or_init(|| Xrender::open().ok()).as_ref()
+}
+
+/// Converts to RENDER's 16.16 fixed point numbers.
+fn fixed(f: f64) -> XFixed {
+    (f * 65536.0).round() as XFixed
+}
+
+/// The picture format of drawables with the given visual, or `None` if the X server behind
+/// `display` doesn't support RENDER.
+pub unsafe fn visual_format(
+    display: *mut Display,
+    visual: *const xlib::Visual,
+) -> Option<*const XRenderPictFormat> {
+    let xrender = xrender()?;
+    let (mut event_base, mut error_base) = (0, 0);
+    if (xrender.XRenderQueryExtension)(display, &mut event_base, &mut error_base) == 0 {
+        return None;
+    }
+    let format = (xrender.XRenderFindVisualFormat)(display, visual);
+    match format.is_null() {
+        true => None,
+        false => Some(format),
+    }
+}
+
+/// Draws the whole of `src`, which is `src_size` pixels large, onto `dst` scaled to fill
+/// `dst_rect`. Both drawables must have the picture format `format`.
+pub unsafe fn composite_scaled(
+    display: *mut Display,
+    format: *const XRenderPictFormat,
+    src: Drawable,
+    src_size: (u32, u32),
+    dst: Drawable,
+    dst_rect: Rect,
+    filter: ScaleFilter,
+) {
+    let xrender = xrender().expect("libXrender used before being loaded");
+    let mut attributes: XRenderPictureAttributes = std::mem::zeroed();
+    // Bilinear filtering samples past the edges, which would otherwise blend in transparent black.
+    attributes.repeat = xrender::RepeatPad;
+    let src_picture =
+        (xrender.XRenderCreatePicture)(display, src, format, xrender::CPRepeat as _, &attributes);
+    let dst_picture = (xrender.XRenderCreatePicture)(display, dst, format, 0, ptr::null());
+
+    // The transform maps destination pixels to the source pixels they sample.
+    let mut transform = XTransform {
+        matrix: [
+            [
+                fixed(f64::from(src_size.0) / f64::from(dst_rect.width)),
+                0,
+                0,
+            ],
+            [
+                0,
+                fixed(f64::from(src_size.1) / f64::from(dst_rect.height)),
+                0,
+            ],
+            [0, 0, fixed(1.0)],
+        ],
+    };
+    (xrender.XRenderSetPictureTransform)(display, src_picture, &mut transform);
+    let filter_name: &[u8] = match filter {
+        ScaleFilter::Nearest => b"nearest\0",
+        ScaleFilter::Bilinear => b"bilinear\0",
+    };
+    (xrender.XRenderSetPictureFilter)(
+        display,
+        src_picture,
+        filter_name.as_ptr() as *const c_char,
+        ptr::null_mut::<c_int>(),
+        0,
+    );
+
+    (xrender.XRenderComposite)(
+        display,
+        xrender::PictOpSrc,
+        src_picture,
+        0,
+        dst_picture,
+        0,
+        0,
+        0,
+        0,
+        dst_rect.x,
+        dst_rect.y,
+        dst_rect.width,
+        dst_rect.height,
+    );
+    (xrender.XRenderFreePicture)(display, src_picture);
+    (xrender.XRenderFreePicture)(display, dst_picture);
+}
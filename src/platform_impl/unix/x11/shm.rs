@@ -0,0 +1,117 @@
+//! Pixel storage backed by a SysV shared memory segment, presented through the MIT-SHM extension.
+
+use std::{
+    os::raw::c_int,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+use x11_dl::{
+    xlib::{self, Display, XErrorEvent},
+    xshm::{XShmSegmentInfo, Xext},
+};
+
+/// Lazily loads `libXext`, returning `None` if it isn't installed.
+pub fn xext() -> Option<&'static Xext> {
+    static XEXT: OnceLock<Option<Xext>> = OnceLock::new();
+    XEXT.get_or_init(|| Xext::open().ok()).as_ref()
+}
+
+/// Set by `attach_error_handler` if the server failed to attach a segment.
+static ATTACH_FAILED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" fn attach_error_handler(_: *mut Display, _: *mut XErrorEvent) -> c_int {
+    ATTACH_FAILED.store(true, Ordering::SeqCst);
+    0
+}
+
+pub struct Segment {
+    /// Boxed so that its address stays stable; `XShmPutImage` finds it through `XImage::obdata`.
+    info: Box<XShmSegmentInfo>,
+    len: usize,
+}
+
+impl Segment {
+    /// Allocates a `len`-byte segment and attaches it to the X server behind `display`.
+    ///
+    /// Returns `None` if the MIT-SHM extension isn't available, or if the server can't attach the
+    /// segment (which is always the case for remote connections).
+    pub unsafe fn new(display: *mut Display, len: usize) -> Option<Segment> {
+        let xext = xext()?;
+        if len == 0 || (xext.XShmQueryExtension)(display) == 0 {
+            return None;
+        }
+
+        let shmid = libc::shmget(libc::IPC_PRIVATE, len, libc::IPC_CREAT | 0o600);
+        if shmid == -1 {
+            return None;
+        }
+        let shmaddr = libc::shmat(shmid, ptr::null(), 0);
+        if shmaddr as isize == -1 {
+            libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+            return None;
+        }
+        let mut info = Box::new(XShmSegmentInfo {
+            shmseg: 0,
+            shmid,
+            shmaddr: shmaddr as _,
+            readOnly: xlib::True,
+        });
+
+        // Attach failures are reported asynchronously through the error handler, which by default
+        // terminates the process. Trap them instead, and sync so that any error has arrived by
+        // the time we check for it.
+        static ERROR_HANDLER_LOCK: Mutex<()> = Mutex::new(());
+        let attached = {
+            let _guard = ERROR_HANDLER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let xlib = super::xlib();
+            ATTACH_FAILED.store(false, Ordering::SeqCst);
+            let prev_handler = (xlib.XSetErrorHandler)(Some(attach_error_handler));
+            let status = (xext.XShmAttach)(display, &mut *info);
+            (xlib.XSync)(display, xlib::False);
+            (xlib.XSetErrorHandler)(prev_handler);
+            status != 0 && !ATTACH_FAILED.load(Ordering::SeqCst)
+        };
+
+        // Mark the segment for deletion now, so that it gets cleaned up once both we and the
+        // server have detached from it, even if the process dies unexpectedly.
+        libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut());
+
+        if attached {
+            Some(Segment { info, len })
+        } else {
+            libc::shmdt(shmaddr);
+            None
+        }
+    }
+
+    /// The segment info to store in the `obdata` field of images using this segment.
+    pub fn info_ptr(&self) -> *mut XShmSegmentInfo {
+        &*self.info as *const XShmSegmentInfo as *mut XShmSegmentInfo
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.info.shmaddr as *mut u8
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.as_ptr(), self.len) }
+    }
+
+    /// Detaches the segment from both the X server and this process.
+    ///
+    /// The segment must not be used afterwards.
+    pub unsafe fn release(&mut self, display: *mut Display) {
+        if let Some(xext) = xext() {
+            (xext.XShmDetach)(display, &mut *self.info);
+            (super::xlib().XSync)(display, xlib::False);
+        }
+        libc::shmdt(self.info.shmaddr as _);
+    }
+}
@@ -1,116 +1,259 @@
-use crate::{PixelBufferCreationError, PixelBufferFormatSupported, PixelBufferFormatType};
+use crate::{
+    memory::Layout,
+    rect::{BlitArea, Rect},
+    scale::ScaleFilter,
+    BlitError, PixelBufferFormatSupported, PixelBufferFormatType,
+};
 use raw_window_handle::{windows::WindowsHandle, RawWindowHandle};
 use std::{convert::TryInto, io, ptr};
 use winapi::{
-    shared::windef::{HBITMAP, HWND},
+    shared::{
+        basetsd::LONG_PTR,
+        windef::{HBITMAP, HWND, POINT, RECT, SIZE},
+    },
     um::{
-        wingdi::{self, BITMAP, BITMAPINFOHEADER},
+        wingdi::{self, BITMAP, BITMAPINFOHEADER, BLENDFUNCTION},
         winuser,
     },
 };
 
-#[cfg(feature = "rayon")]
-use rayon::prelude::*;
-
 pub struct PixelBuffer {
     handle: HBITMAP,
+    /// The DIB section's bitmap, which may be larger than the pixel buffer.
     bitmap: BITMAP,
-    len: usize,
+    width: u32,
+    height: u32,
     hwnd: HWND,
+    /// Whether the window is a layered window presenting the buffer's alpha channel.
+    alpha: bool,
 }
 
 unsafe impl Send for PixelBuffer {}
 
-fn px_cast(u: u32) -> i32 {
-    u.try_into()
-        .expect("Pixel value too large; must be less than 2,147,483,647")
+fn px_cast(u: u32) -> Result<i32, BlitError> {
+    u.try_into().map_err(|_| BlitError::DimensionsTooLarge)
 }
 
 impl PixelBufferFormatSupported for crate::BGRA {}
 impl PixelBufferFormatSupported for crate::BGR {}
 pub type NativeFormat = crate::BGRA;
 
-fn hwnd(handle: RawWindowHandle) -> HWND {
+fn hwnd(handle: RawWindowHandle) -> Result<HWND, BlitError> {
     match handle {
-        RawWindowHandle::Windows(WindowsHandle { hwnd, .. }) => hwnd as _,
-        _ => panic!("Unsupported window handle type"),
+        RawWindowHandle::Windows(WindowsHandle { hwnd, .. }) => Ok(hwnd as _),
+        _ => Err(BlitError::UnsupportedHandle),
     }
 }
 
+/// Creates a top-down DIB section of the given size, or a null bitmap if it would be empty.
+///
+/// Top-down DIBs store their rows in the same order as every other backend, so the buffer's
+/// memory can be handed out as a single contiguous slice.
+unsafe fn create_bitmap(
+    width: u32,
+    height: u32,
+    bit_count: u16,
+) -> Result<(HBITMAP, BITMAP), BlitError> {
+    let handle: HBITMAP;
+    let bitmap: BITMAP;
+    if width != 0 && height != 0 {
+        handle = {
+            let info = BITMAPINFOHEADER {
+                biSize: std::mem::size_of::<BITMAPINFOHEADER>() as _,
+                biWidth: px_cast(width)?,
+                // A negative height makes the DIB top-down.
+                biHeight: -px_cast(height)?,
+                biPlanes: 1,
+                biBitCount: bit_count,
+                biCompression: wingdi::BI_RGB,
+                biSizeImage: 0,
+                biXPelsPerMeter: 1,
+                biYPelsPerMeter: 1,
+                biClrUsed: 0,
+                biClrImportant: 0,
+            };
+            let dc = winuser::GetDC(ptr::null_mut());
+            let dib_section = wingdi::CreateDIBSection(
+                dc,
+                &info as *const BITMAPINFOHEADER as _,
+                wingdi::DIB_RGB_COLORS,
+                &mut ptr::null_mut(),
+                ptr::null_mut(),
+                0,
+            );
+            winuser::ReleaseDC(ptr::null_mut(), dc);
+            dib_section
+        };
+
+        if handle.is_null() {
+            return Err(BlitError::AllocationFailed(io::Error::last_os_error()));
+        }
+        bitmap = {
+            let mut bitmap: BITMAP = std::mem::zeroed();
+            let bytes_written = wingdi::GetObjectW(
+                handle as _,
+                std::mem::size_of::<BITMAP>() as i32,
+                &mut bitmap as *mut BITMAP as *mut _,
+            );
+            if bytes_written == 0 {
+                let error = io::Error::last_os_error();
+                wingdi::DeleteObject(handle as _);
+                return Err(BlitError::AllocationFailed(error));
+            }
+            bitmap
+        };
+    } else {
+        handle = ptr::null_mut();
+        bitmap = BITMAP {
+            bmType: 0,
+            bmWidth: px_cast(width)?,
+            bmHeight: px_cast(height)?,
+            bmWidthBytes: (width as usize)
+                .checked_mul(usize::from(bit_count) / 8)
+                .and_then(|len| len.try_into().ok())
+                .ok_or(BlitError::DimensionsTooLarge)?,
+            bmPlanes: 1,
+            bmBitsPixel: bit_count,
+            bmBits: ptr::null_mut(),
+        };
+    }
+    Ok((handle, bitmap))
+}
+
 impl PixelBuffer {
     pub unsafe fn new(
         width: u32,
         height: u32,
         format: PixelBufferFormatType,
         raw_window_handle: RawWindowHandle,
-    ) -> Result<PixelBuffer, PixelBufferCreationError> {
+    ) -> Result<PixelBuffer, BlitError> {
+        let hwnd = hwnd(raw_window_handle)?;
         let bit_count = match format {
             PixelBufferFormatType::BGRA => 32,
             PixelBufferFormatType::BGR => 24,
-            _ => return Err(PixelBufferCreationError::FormatNotSupported),
+            _ => return Err(BlitError::FormatNotSupported),
         };
-        let handle: HBITMAP;
-        let bitmap: BITMAP;
-        if width != 0 && height != 0 {
-            handle = {
-                let info = BITMAPINFOHEADER {
-                    biSize: std::mem::size_of::<BITMAPINFOHEADER>() as _,
-                    biWidth: px_cast(width),
-                    biHeight: px_cast(height),
-                    biPlanes: 1,
-                    biBitCount: bit_count,
-                    biCompression: wingdi::BI_RGB,
-                    biSizeImage: 0,
-                    biXPelsPerMeter: 1,
-                    biYPelsPerMeter: 1,
-                    biClrUsed: 0,
-                    biClrImportant: 0,
-                };
-                let dc = winuser::GetDC(ptr::null_mut());
-                let dib_section = wingdi::CreateDIBSection(
-                    dc,
-                    &info as *const BITMAPINFOHEADER as _,
-                    wingdi::DIB_RGB_COLORS,
-                    &mut ptr::null_mut(),
-                    ptr::null_mut(),
-                    0,
-                );
-                winuser::ReleaseDC(ptr::null_mut(), dc);
-                dib_section
-            };
-
-            assert_ne!(std::ptr::null_mut(), handle);
-            bitmap = {
-                let mut bitmap: BITMAP = std::mem::zeroed();
-                let bytes_written = wingdi::GetObjectW(
-                    handle as _,
-                    std::mem::size_of::<BITMAP>() as i32,
-                    &mut bitmap as *mut BITMAP as *mut _,
-                );
-                assert_ne!(0, bytes_written);
-                bitmap
-            };
-        } else {
-            handle = ptr::null_mut();
-            bitmap = BITMAP {
-                bmType: 0,
-                bmWidth: px_cast(width),
-                bmHeight: px_cast(height),
-                bmWidthBytes: px_cast(width * bit_count as u32 / 8),
-                bmPlanes: 1,
-                bmBitsPixel: bit_count,
-                bmBits: ptr::null_mut(),
-            };
-        }
+        let (handle, bitmap) = create_bitmap(width, height, bit_count)?;
         Ok(PixelBuffer {
             handle,
             bitmap,
-            len: (bitmap.bmWidthBytes * bitmap.bmHeight) as usize,
-            hwnd: hwnd(raw_window_handle),
+            width,
+            height,
+            hwnd,
+            alpha: false,
         })
     }
-    pub unsafe fn blit(&self, handle: RawWindowHandle) -> io::Result<()> {
-        self.blit_rect((0, 0), (0, 0), (self.width(), self.height()), handle)
+
+    pub fn capacity(&self) -> (u32, u32) {
+        // Top-down bitmaps may report a negative height.
+        (
+            self.bitmap.bmWidth as u32,
+            self.bitmap.bmHeight.unsigned_abs(),
+        )
+    }
+
+    /// Changes the buffer's size without touching its memory. The size must fit in `capacity`.
+    ///
+    /// The buffer always occupies the top-left corner of the bitmap, which is the start of its
+    /// memory, so blits can address it with the same coordinates regardless of its size.
+    pub fn set_size(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Replaces the buffer's bitmap with a zeroed one of the given size.
+    pub unsafe fn reallocate(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        let (handle, bitmap) = create_bitmap(width, height, self.bitmap.bmBitsPixel)?;
+        wingdi::DeleteObject(self.handle as _);
+        self.handle = handle;
+        self.bitmap = bitmap;
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+
+    /// Turns the window into a layered window, which presents the buffer's premultiplied alpha
+    /// channel, or back into a regular one.
+    pub unsafe fn set_alpha(&mut self, alpha: bool) -> Result<(), BlitError> {
+        let style = winuser::GetWindowLongPtrW(self.hwnd, winuser::GWL_EXSTYLE);
+        let layered = winuser::WS_EX_LAYERED as LONG_PTR;
+        let new_style = match alpha {
+            true => style | layered,
+            false => style & !layered,
+        };
+        if new_style != style {
+            // The previous style is returned on success, which may well be zero, so check that
+            // the style changed instead.
+            winuser::SetWindowLongPtrW(self.hwnd, winuser::GWL_EXSTYLE, new_style);
+            if winuser::GetWindowLongPtrW(self.hwnd, winuser::GWL_EXSTYLE) != new_style {
+                return Err(io::Error::last_os_error().into());
+            }
+        }
+        self.alpha = alpha;
+        Ok(())
+    }
+
+    /// Presents the whole buffer onto the layered window, resizing the window to match.
+    ///
+    /// Layered windows don't keep any contents of their own to draw parts of the buffer onto.
+    unsafe fn update_layered(&self) -> Result<(), BlitError> {
+        let mut size = SIZE {
+            cx: px_cast(self.width)?,
+            cy: px_cast(self.height)?,
+        };
+        let mut src_pos = POINT { x: 0, y: 0 };
+        let mut blend = BLENDFUNCTION {
+            BlendOp: wingdi::AC_SRC_OVER,
+            BlendFlags: 0,
+            SourceConstantAlpha: 255,
+            AlphaFormat: wingdi::AC_SRC_ALPHA,
+        };
+        let hdc = winuser::GetDC(ptr::null_mut());
+
+        let src_dc = wingdi::CreateCompatibleDC(hdc);
+        let prev_bmp = wingdi::SelectObject(src_dc, self.handle as _);
+        let result = winuser::UpdateLayeredWindow(
+            self.hwnd,
+            hdc,
+            ptr::null_mut(),
+            &mut size,
+            src_dc,
+            &mut src_pos,
+            0,
+            &mut blend,
+            winuser::ULW_ALPHA,
+        );
+        let error = io::Error::last_os_error();
+
+        wingdi::SelectObject(src_dc, prev_bmp);
+        wingdi::DeleteDC(src_dc);
+        winuser::ReleaseDC(ptr::null_mut(), hdc);
+
+        if result != 0 {
+            Ok(())
+        } else {
+            Err(BlitError::Io(error))
+        }
+    }
+
+    fn check_hwnd(&self, handle: RawWindowHandle) -> Result<HWND, BlitError> {
+        match hwnd(handle)? {
+            hwnd if hwnd == self.hwnd => Ok(hwnd),
+            _ => Err(BlitError::WindowMismatch),
+        }
+    }
+
+    pub unsafe fn window_size(&self, handle: RawWindowHandle) -> Result<(u32, u32), BlitError> {
+        let hwnd = self.check_hwnd(handle)?;
+        let mut rect: RECT = std::mem::zeroed();
+        if winuser::GetClientRect(hwnd, &mut rect) == 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok((
+            (rect.right - rect.left) as u32,
+            (rect.bottom - rect.top) as u32,
+        ))
     }
 
     pub unsafe fn blit_rect(
@@ -119,25 +262,30 @@ impl PixelBuffer {
         dst_pos: (u32, u32),
         blit_size: (u32, u32),
         handle: RawWindowHandle,
-    ) -> io::Result<()> {
-        if self.handle == ptr::null_mut() {
+    ) -> Result<(), BlitError> {
+        let hwnd = self.check_hwnd(handle)?;
+        if self.handle.is_null() {
             return Ok(());
         }
-        let hwnd = hwnd(handle);
-        assert_eq!(hwnd, self.hwnd);
+        if self.alpha {
+            return self.update_layered();
+        }
+        let (src_x, src_y) = (px_cast(src_pos.0)?, px_cast(src_pos.1)?);
+        let (dst_x, dst_y) = (px_cast(dst_pos.0)?, px_cast(dst_pos.1)?);
+        let (width, height) = (px_cast(blit_size.0)?, px_cast(blit_size.1)?);
         let hdc = winuser::GetDC(hwnd as _);
 
         let src_dc = wingdi::CreateCompatibleDC(hdc);
         let prev_bmp = wingdi::SelectObject(src_dc, self.handle as _);
         let result = wingdi::BitBlt(
             hdc,
-            px_cast(src_pos.0),
-            px_cast(src_pos.1),
-            px_cast(blit_size.0),
-            px_cast(blit_size.1),
+            dst_x,
+            dst_y,
+            width,
+            height,
             src_dc,
-            px_cast(dst_pos.0),
-            px_cast(dst_pos.1),
+            src_x,
+            src_y,
             wingdi::SRCCOPY,
         );
         let error = io::Error::last_os_error();
@@ -149,110 +297,202 @@ impl PixelBuffer {
         if result != 0 {
             Ok(())
         } else {
-            Err(error)
+            Err(BlitError::Io(error))
         }
     }
 
-    pub fn bits_per_pixel(&self) -> usize {
-        self.bitmap.bmBitsPixel as usize
+    pub unsafe fn blit_rects(
+        &self,
+        areas: &[BlitArea],
+        handle: RawWindowHandle,
+    ) -> Result<(), BlitError> {
+        if self.alpha {
+            // Every blit presents the whole buffer, so once is enough.
+            return match areas.first() {
+                Some(&(src_pos, dst_pos, size)) => self.blit_rect(src_pos, dst_pos, size, handle),
+                None => Ok(()),
+            };
+        }
+        for &(src_pos, dst_pos, size) in areas {
+            self.blit_rect(src_pos, dst_pos, size, handle)?;
+        }
+        Ok(())
     }
 
-    pub fn bytes_per_pixel(&self) -> usize {
-        self.bits_per_pixel() / 8
-    }
+    /// Blits `src` onto the window with `StretchBlt`, scaled to fill `dst`.
+    ///
+    /// GDI has no bilinear filtering, so `Bilinear` uses halftoning, which averages the source
+    /// pixels each destination pixel covers.
+    pub unsafe fn blit_scaled(
+        &self,
+        src: Rect,
+        dst: Rect,
+        filter: ScaleFilter,
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        let hwnd = self.check_hwnd(handle)?;
+        // Layered windows can only be updated from a bitmap of their own size.
+        if self.alpha {
+            return Ok(false);
+        }
+        if self.handle.is_null() {
+            return Ok(true);
+        }
+        let (src_width, src_height) = (px_cast(src.width)?, px_cast(src.height)?);
+        let (dst_width, dst_height) = (px_cast(dst.width)?, px_cast(dst.height)?);
+        let hdc = winuser::GetDC(hwnd);
 
-    pub fn width(&self) -> u32 {
-        self.bitmap.bmWidth as u32
-    }
+        let src_dc = wingdi::CreateCompatibleDC(hdc);
+        let prev_bmp = wingdi::SelectObject(src_dc, self.handle as _);
+        let prev_mode = wingdi::SetStretchBltMode(
+            hdc,
+            match filter {
+                ScaleFilter::Nearest => wingdi::COLORONCOLOR,
+                ScaleFilter::Bilinear => wingdi::HALFTONE,
+            },
+        );
+        // Halftoning requires resetting the brush origin after switching to it.
+        wingdi::SetBrushOrgEx(hdc, 0, 0, ptr::null_mut());
+        let result = wingdi::StretchBlt(
+            hdc,
+            dst.x,
+            dst.y,
+            dst_width,
+            dst_height,
+            src_dc,
+            src.x,
+            src.y,
+            src_width,
+            src_height,
+            wingdi::SRCCOPY,
+        );
+        let error = io::Error::last_os_error();
 
-    pub fn row_len(&self) -> usize {
-        self.bitmap.bmWidthBytes as usize
-    }
+        wingdi::SetStretchBltMode(hdc, prev_mode);
+        wingdi::SelectObject(src_dc, prev_bmp);
+        wingdi::DeleteDC(src_dc);
+        winuser::ReleaseDC(hwnd, hdc);
 
-    pub fn height(&self) -> u32 {
-        self.bitmap.bmHeight as u32
+        match result {
+            0 => Err(BlitError::Io(error)),
+            _ => Ok(true),
+        }
     }
 
-    fn bytes(&self) -> &[u8] {
-        if self.handle == ptr::null_mut() {
-            return &[];
+    /// Presents `size` pixels of the image in `data` straight out of the caller's memory.
+    ///
+    /// DIB rows must be a multiple of four bytes long, so this only works for images whose
+    /// stride is a multiple of both four and the pixel size.
+    pub unsafe fn blit_slice(
+        &self,
+        data: &[u8],
+        layout: Layout,
+        size: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        let hwnd = self.check_hwnd(handle)?;
+        let bytes_per_pixel = layout.bytes_per_pixel;
+        // Layered windows can only be updated from a bitmap.
+        if self.alpha
+            || !layout.stride.is_multiple_of(4)
+            || !layout.stride.is_multiple_of(bytes_per_pixel)
+        {
+            return Ok(false);
         }
-        unsafe { std::slice::from_raw_parts(self.bitmap.bmBits as *const u8, self.len) }
-    }
+        let stride_pixels = (layout.stride / bytes_per_pixel)
+            .try_into()
+            .map_err(|_| BlitError::DimensionsTooLarge)?;
+        let (width, height) = (px_cast(size.0)?, px_cast(size.1)?);
+        let info = BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as _,
+            biWidth: px_cast(stride_pixels)?,
+            // A negative height makes the DIB top-down, like the image. Leaving out the rows
+            // below the blitted ones means the source rectangle always covers the whole DIB,
+            // which sidesteps GDI's inconsistent handling of source offsets in top-down DIBs.
+            biHeight: -height,
+            biPlanes: 1,
+            biBitCount: (bytes_per_pixel * 8) as u16,
+            biCompression: wingdi::BI_RGB,
+            biSizeImage: 0,
+            biXPelsPerMeter: 1,
+            biYPelsPerMeter: 1,
+            biClrUsed: 0,
+            biClrImportant: 0,
+        };
 
-    fn bytes_mut(&mut self) -> &mut [u8] {
-        if self.handle == ptr::null_mut() {
-            return &mut [];
+        let hdc = winuser::GetDC(hwnd);
+        let lines = wingdi::StretchDIBits(
+            hdc,
+            0,
+            0,
+            width,
+            height,
+            0,
+            0,
+            width,
+            height,
+            data.as_ptr() as _,
+            &info as *const BITMAPINFOHEADER as _,
+            wingdi::DIB_RGB_COLORS,
+            wingdi::SRCCOPY,
+        );
+        let error = io::Error::last_os_error();
+        winuser::ReleaseDC(hwnd, hdc);
+
+        match lines {
+            0 => Err(BlitError::Io(error)),
+            _ => Ok(true),
         }
-        unsafe { std::slice::from_raw_parts_mut(self.bitmap.bmBits as *mut u8, self.len) }
     }
 
-    pub fn row(&self, row: u32) -> Option<&[u8]> {
-        let index = self.tlo_to_blo(row) as usize * self.row_len();
-        let pixel_len = self.width() as usize * self.bytes_per_pixel();
-        self.bytes().get(index..index + pixel_len)
+    /// Staging buffers can be blitted onto the window on their own.
+    pub unsafe fn blit_staged(
+        &self,
+        _: &[u8],
+        _: Layout,
+        _: (u32, u32),
+        _: (u32, u32),
+        _: (u32, u32),
+        handle: RawWindowHandle,
+    ) -> Result<bool, BlitError> {
+        self.check_hwnd(handle)?;
+        Ok(false)
     }
 
-    pub fn row_mut(&mut self, row: u32) -> Option<&mut [u8]> {
-        let index = self.tlo_to_blo(row) as usize * self.row_len();
-        let pixel_len = self.width() as usize * self.bytes_per_pixel();
-        self.bytes_mut().get_mut(index..index + pixel_len)
+    pub fn bits_per_pixel(&self) -> usize {
+        self.bitmap.bmBitsPixel as usize
     }
 
-    pub fn rows<'a>(&'a self) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a [u8]> {
-        let stride = match self.row_len() {
-            0 => 1,
-            l => l,
-        };
-        let pixel_len = self.width() as usize * self.bytes_per_pixel();
-        self.bytes()
-            .chunks(stride)
-            .rev()
-            .map(move |row| &row[..pixel_len])
+    pub fn width(&self) -> u32 {
+        self.width
     }
 
-    pub fn rows_mut<'a>(
-        &'a mut self,
-    ) -> impl ExactSizeIterator + DoubleEndedIterator<Item = &'a mut [u8]> {
-        let stride = match self.row_len() {
-            0 => 1,
-            l => l,
-        };
-        let pixel_len = self.width() as usize * self.bytes_per_pixel();
-        self.bytes_mut()
-            .chunks_mut(stride)
-            .rev()
-            .map(move |row| &mut row[..pixel_len])
+    pub fn row_len(&self) -> usize {
+        self.bitmap.bmWidthBytes as usize
     }
 
-    #[cfg(feature = "rayon")]
-    pub fn par_rows<'a>(&'a self) -> impl IndexedParallelIterator<Item = &'a [u8]> {
-        let stride = match self.row_len() {
-            0 => 1,
-            l => l,
-        };
-        let pixel_len = self.width() as usize * self.bytes_per_pixel();
-        self.bytes()
-            .par_chunks(stride)
-            .rev()
-            .map(move |row| &row[..pixel_len])
+    pub fn height(&self) -> u32 {
+        self.height
     }
 
-    #[cfg(feature = "rayon")]
-    pub fn par_rows_mut<'a>(&'a mut self) -> impl IndexedParallelIterator<Item = &'a mut [u8]> {
-        let stride = match self.row_len() {
-            0 => 1,
-            l => l,
-        };
-        let pixel_len = self.width() as usize * self.bytes_per_pixel();
-        self.bytes_mut()
-            .par_chunks_mut(stride)
-            .rev()
-            .map(move |row| &mut row[..pixel_len])
+    /// The length of the part of the bitmap's memory holding the buffer's rows.
+    fn bytes_len(&self) -> usize {
+        self.row_len() * self.height as usize
     }
 
-    fn tlo_to_blo(&self, tlo_row: u32) -> u32 {
-        self.height() - 1 - tlo_row
+    pub fn bytes(&self) -> &[u8] {
+        if self.handle.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.bitmap.bmBits as *const u8, self.bytes_len()) }
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        if self.handle.is_null() {
+            return &mut [];
+        }
+        let len = self.bytes_len();
+        unsafe { std::slice::from_raw_parts_mut(self.bitmap.bmBits as *mut u8, len) }
     }
 }
 
@@ -338,7 +578,7 @@ mod tests {
         unsafe {
             let desktop_wnd = from_hwnd(GetDesktopWindow());
             let pb = PixelBuffer::new(31, 31, PixelBufferFormatType::BGR, desktop_wnd).unwrap();
-            let _res = pb.blit(desktop_wnd);
+            let _res = pb.blit_rect((0, 0), (0, 0), (31, 31), desktop_wnd);
         }
 
         // It is expected that all resources have been released at this point.
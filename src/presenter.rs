@@ -0,0 +1,263 @@
+//! Handing frames over from a render thread to the thread that presents them.
+
+use crate::{damage::Damage, BlitError, BlitTarget, PixelBufferFormat, PixelBufferTyped, Rect};
+use std::{
+    marker::PhantomData,
+    mem, ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// A submitted frame that hasn't been presented yet.
+struct Frame<P: PixelBufferFormat> {
+    buffer: PixelBufferTyped<P>,
+    /// The parts of the frame that changed since the frame before it, or `None` if every part may
+    /// have.
+    damage: Option<Damage>,
+}
+
+impl<P: PixelBufferFormat> Frame<P> {
+    /// Takes over the damage of `older`, a frame that got replaced before it could be presented,
+    /// and returns its buffer.
+    fn absorb(&mut self, older: Frame<P>) -> PixelBufferTyped<P> {
+        self.damage = match (self.damage.take(), older.damage) {
+            (Some(mut damage), Some(older)) => {
+                for rect in older.rects() {
+                    damage.add(*rect);
+                }
+                Some(damage)
+            }
+            _ => None,
+        };
+        older.buffer
+    }
+}
+
+/// Holds at most one frame waiting to be presented, and one buffer waiting to be reused.
+struct Mailbox<P: PixelBufferFormat> {
+    latest: AtomicPtr<Frame<P>>,
+    spare: AtomicPtr<PixelBufferTyped<P>>,
+    /// `AtomicPtr` is `Send` and `Sync` whatever it points to. Like a `Mutex`, the mailbox may
+    /// only be shared between threads if the buffers can be sent between them.
+    _buffers: PhantomData<Mutex<PixelBufferTyped<P>>>,
+}
+
+impl<P: PixelBufferFormat> Mailbox<P> {
+    fn take(&self) -> Option<Box<Frame<P>>> {
+        let frame = self.latest.swap(ptr::null_mut(), Ordering::AcqRel);
+        match frame.is_null() {
+            true => None,
+            false => Some(unsafe { Box::from_raw(frame) }),
+        }
+    }
+
+    /// Puts `frame` in the mailbox, merging it with the frame already there. `frame` is the newer
+    /// of the two unless `restoring` is set, which is the case for frames that failed to present.
+    fn put(&self, mut frame: Box<Frame<P>>, mut restoring: bool) {
+        loop {
+            if let Some(mut other) = self.take() {
+                if restoring {
+                    mem::swap(&mut frame, &mut other);
+                }
+                self.recycle(frame.absorb(*other));
+            }
+            let raw = Box::into_raw(frame);
+            match self.latest.compare_exchange(
+                ptr::null_mut(),
+                raw,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(_) => {
+                    // Another thread submitted a frame in the meantime, which makes it the newer
+                    // one.
+                    frame = unsafe { Box::from_raw(raw) };
+                    restoring = true;
+                }
+            }
+        }
+    }
+
+    /// Keeps `buffer` around for reuse, dropping the buffer that was kept before.
+    fn recycle(&self, buffer: PixelBufferTyped<P>) {
+        let old = self
+            .spare
+            .swap(Box::into_raw(Box::new(buffer)), Ordering::AcqRel);
+        if !old.is_null() {
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    fn take_spare(&self) -> Option<PixelBufferTyped<P>> {
+        let buffer = self.spare.swap(ptr::null_mut(), Ordering::AcqRel);
+        match buffer.is_null() {
+            true => None,
+            false => Some(*unsafe { Box::from_raw(buffer) }),
+        }
+    }
+}
+
+impl<P: PixelBufferFormat> Drop for Mailbox<P> {
+    fn drop(&mut self) {
+        drop(self.take());
+        drop(self.take_spare());
+    }
+}
+
+/// A mailbox for presenting frames rendered on another thread.
+///
+/// Blitting needs a reference to the window, which usually has to stay on the thread running the
+/// event loop. Render threads instead [`submit`](Self::submit) finished buffers to a `Presenter`,
+/// and the event loop's thread calls [`present_latest`](Self::present_latest), typically when
+/// handling a redraw request. Clones of a `Presenter` share the same mailbox.
+///
+/// The mailbox only ever holds the latest frame. Submitting a frame before the previous one got
+/// presented replaces it, so a render thread that's faster than the display never builds up
+/// latency. Buffers that are done being used, whether they got presented or replaced, can be
+/// taken back with [`take_spare`](Self::take_spare) instead of allocating new ones.
+///
+/// Neither submitting nor presenting ever blocks on the other thread.
+pub struct Presenter<P: PixelBufferFormat> {
+    mailbox: Arc<Mailbox<P>>,
+}
+
+impl<P: PixelBufferFormat> Presenter<P> {
+    /// Creates a presenter with an empty mailbox.
+    pub fn new() -> Presenter<P> {
+        Presenter {
+            mailbox: Arc::new(Mailbox {
+                latest: AtomicPtr::new(ptr::null_mut()),
+                spare: AtomicPtr::new(ptr::null_mut()),
+                _buffers: PhantomData,
+            }),
+        }
+    }
+
+    /// Submits a finished frame, to be presented in its entirety. Replaces the frame waiting to be
+    /// presented, if there is one.
+    pub fn submit(&self, buffer: PixelBufferTyped<P>) {
+        self.mailbox.put(
+            Box::new(Frame {
+                buffer,
+                damage: None,
+            }),
+            false,
+        );
+    }
+
+    /// Submits a finished frame, of which only the `damage` rectangles changed since the
+    /// previously submitted frame. Replaces the frame waiting to be presented, if there is one,
+    /// in which case the damage of both frames gets presented.
+    ///
+    /// `buffer` must still hold the whole frame, since it may end up presented in full. Only
+    /// submit the first frame, and the first frame after a resize, with
+    /// [`submit`](Self::submit).
+    pub fn submit_damaged(&self, buffer: PixelBufferTyped<P>, damage: &[Rect]) {
+        let bounds = Rect::from_size(buffer.width(), buffer.height());
+        let mut frame_damage = Damage::default();
+        for rect in damage.iter().filter_map(|rect| rect.intersection(&bounds)) {
+            frame_damage.add(rect);
+        }
+        self.mailbox.put(
+            Box::new(Frame {
+                buffer,
+                damage: Some(frame_damage),
+            }),
+            false,
+        );
+    }
+
+    /// Takes back a buffer that's done being used, if there is one, so that it can be drawn into
+    /// again. The buffer holds some earlier frame.
+    ///
+    /// Only the most recently freed buffer is kept around. Older ones get dropped.
+    pub fn take_spare(&self) -> Option<PixelBufferTyped<P>> {
+        self.mailbox.take_spare()
+    }
+
+    /// Blits the latest submitted frame onto `window`, if one was submitted since the last
+    /// present.
+    ///
+    /// Returns whether a frame was presented. Frames that got replaced by a newer one before this
+    /// was called are never presented.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::blit`]. The frame stays in the mailbox if
+    /// the blit fails, unless a newer one has been submitted.
+    pub fn present_latest<H: BlitTarget>(&self, window: &H) -> Result<bool, BlitError> {
+        let frame = match self.mailbox.take() {
+            Some(frame) => frame,
+            None => return Ok(false),
+        };
+        let result = match &frame.damage {
+            Some(damage) => frame.buffer.blit_rects(damage.rects(), window),
+            None => frame.buffer.blit(window),
+        };
+        match result {
+            Ok(()) => {
+                self.mailbox.recycle(frame.buffer);
+                Ok(true)
+            }
+            Err(e) => {
+                self.mailbox.put(frame, true);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<P: PixelBufferFormat> Default for Presenter<P> {
+    fn default() -> Presenter<P> {
+        Presenter::new()
+    }
+}
+
+impl<P: PixelBufferFormat> Clone for Presenter<P> {
+    fn clone(&self) -> Presenter<P> {
+        Presenter {
+            mailbox: self.mailbox.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadlessWindow, PixelBufferFormatType, BGRA};
+
+    #[test]
+    fn presenter_drops_stale_frames() {
+        let window = HeadlessWindow::new(3, 1, PixelBufferFormatType::BGRA);
+        let presenter = Presenter::<BGRA>::new();
+        assert!(!presenter.present_latest(&window).unwrap());
+
+        let frames: Vec<_> = (1..=3)
+            .map(|frame| {
+                let mut buffer = PixelBufferTyped::<BGRA>::new(3, 1, &window).unwrap();
+                buffer.pixels_mut().fill(BGRA::new(frame, 0, 0, 0));
+                buffer
+            })
+            .collect();
+        let mut frames = frames.into_iter();
+        presenter.submit(frames.next().unwrap());
+        assert!(presenter.present_latest(&window).unwrap());
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                presenter.submit_damaged(frames.next().unwrap(), &[Rect::new(0, 0, 1, 1)]);
+                presenter.submit_damaged(frames.next().unwrap(), &[Rect::new(1, 0, 1, 1)]);
+            });
+        });
+        assert!(presenter.present_latest(&window).unwrap());
+        // Frame 2 never got presented, but its damage did, with the pixels of frame 3.
+        assert_eq!(vec![3, 3, 1], window.first_bytes(0));
+        assert!(!presenter.present_latest(&window).unwrap());
+
+        let spare = presenter.take_spare().unwrap();
+        assert_eq!(BGRA::new(3, 0, 0, 0), spare.row(0).unwrap()[0]);
+        assert!(presenter.take_spare().is_none());
+    }
+}
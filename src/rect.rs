@@ -0,0 +1,184 @@
+/// A rectangle of pixels.
+///
+/// The position is signed, so that rectangles can lie partially outside of a pixel buffer or
+/// window, such as a sprite moving off the edge of the screen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How blits treat source rectangles that extend past the edges of the pixel buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SourceBounds {
+    /// Only the part of the rectangle within the pixel buffer gets blitted.
+    #[default]
+    Clip,
+    /// The blit fails with [`BlitError::OutOfBounds`](crate::BlitError::OutOfBounds).
+    Reject,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A rectangle at the origin with the given size.
+    pub const fn from_size(width: u32, height: u32) -> Rect {
+        Rect::new(0, 0, width, height)
+    }
+
+    /// Whether the rectangle covers no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// The x coordinate one past the rectangle's right edge.
+    pub fn right(&self) -> i64 {
+        i64::from(self.x) + i64::from(self.width)
+    }
+
+    /// The y coordinate one past the rectangle's bottom edge.
+    pub fn bottom(&self) -> i64 {
+        i64::from(self.y) + i64::from(self.height)
+    }
+
+    /// Whether every pixel in `other` is also in `self`. Empty rectangles are contained in every
+    /// rectangle.
+    pub fn contains_rect(&self, other: &Rect) -> bool {
+        other.is_empty()
+            || (self.x <= other.x
+                && self.y <= other.y
+                && other.right() <= self.right()
+                && other.bottom() <= self.bottom())
+    }
+
+    /// The smallest rectangle containing both `self` and `other`. Empty rectangles are ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(
+            x,
+            y,
+            (self.right().max(other.right()) - i64::from(x)).min(i64::from(u32::MAX)) as u32,
+            (self.bottom().max(other.bottom()) - i64::from(y)).min(i64::from(u32::MAX)) as u32,
+        )
+    }
+
+    /// The pixels that are in both `self` and `other`, or `None` if there aren't any.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= i64::from(x) || bottom <= i64::from(y) {
+            return None;
+        }
+        Some(Rect::new(
+            x,
+            y,
+            (right - i64::from(x)) as u32,
+            (bottom - i64::from(y)) as u32,
+        ))
+    }
+}
+
+/// The source position, destination position and size of a blit.
+pub(crate) type BlitArea = ((u32, u32), (u32, u32), (u32, u32));
+
+/// Clips a blit of `src` onto `dst_pos` against the pixel buffer's and the window's bounds.
+///
+/// Returns the clipped source position, destination position and size, or `None` if nothing is
+/// left to blit.
+pub(crate) fn clip_blit(
+    src: Rect,
+    dst_pos: (i32, i32),
+    buffer_size: (u32, u32),
+    window_size: (u32, u32),
+) -> Option<BlitArea> {
+    let clamp = |n: u32| n.min(i32::MAX as u32);
+    let buffer = Rect::from_size(clamp(buffer_size.0), clamp(buffer_size.1));
+    let window = Rect::from_size(clamp(window_size.0), clamp(window_size.1));
+
+    let clipped_src = src.intersection(&buffer)?;
+    let dst = Rect {
+        x: (i64::from(dst_pos.0) + i64::from(clipped_src.x) - i64::from(src.x))
+            .min(i64::from(i32::MAX)) as i32,
+        y: (i64::from(dst_pos.1) + i64::from(clipped_src.y) - i64::from(src.y))
+            .min(i64::from(i32::MAX)) as i32,
+        ..clipped_src
+    };
+    let clipped_dst = dst.intersection(&window)?;
+
+    // Both clipped rectangles lie within non-negative bounds, so their positions are positive.
+    let src_pos = (
+        (clipped_src.x + (clipped_dst.x - dst.x)) as u32,
+        (clipped_src.y + (clipped_dst.y - dst.y)) as u32,
+    );
+    Some((
+        src_pos,
+        (clipped_dst.x as u32, clipped_dst.y as u32),
+        (clipped_dst.width, clipped_dst.height),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_intersection() {
+        let a = Rect::new(-2, -2, 4, 4);
+        assert_eq!(
+            Some(Rect::new(0, 0, 2, 2)),
+            a.intersection(&Rect::from_size(10, 10))
+        );
+        assert_eq!(None, a.intersection(&Rect::new(2, 0, 1, 1)));
+        assert!(Rect::from_size(4, 4).contains_rect(&Rect::new(1, 1, 3, 3)));
+        assert!(!Rect::from_size(4, 4).contains_rect(&Rect::new(1, 1, 4, 3)));
+    }
+
+    #[test]
+    fn clip_blit_negative_destination() {
+        // A 4x4 sprite hanging off the top-left corner of the window.
+        assert_eq!(
+            Some(((3, 2), (0, 0), (1, 2))),
+            clip_blit(Rect::from_size(4, 4), (-3, -2), (4, 4), (8, 8))
+        );
+        // The same sprite, hanging off the bottom-right corner.
+        assert_eq!(
+            Some(((0, 0), (6, 7), (2, 1))),
+            clip_blit(Rect::from_size(4, 4), (6, 7), (4, 4), (8, 8))
+        );
+        assert_eq!(
+            None,
+            clip_blit(Rect::from_size(4, 4), (-4, 0), (4, 4), (8, 8))
+        );
+    }
+
+    #[test]
+    fn clip_blit_source() {
+        // Clipping the source moves the destination along with it.
+        assert_eq!(
+            Some(((0, 1), (3, 2), (2, 3))),
+            clip_blit(Rect::new(-1, 1, 3, 5), (2, 2), (4, 4), (8, 8))
+        );
+        assert_eq!(
+            None,
+            clip_blit(Rect::new(4, 0, 1, 1), (0, 0), (4, 4), (8, 8))
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! Software scaling, for platforms that can't stretch blits themselves.
+
+use crate::{backend::Backend, rect::Rect};
+
+/// How pixels get sampled when a blit changes the size of an image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScaleFilter {
+    /// Each pixel takes the value of the nearest source pixel, which keeps edges sharp. Best for
+    /// pixel art scaled by whole numbers.
+    #[default]
+    Nearest,
+    /// Each pixel blends the four nearest source pixels, which gives smoother results.
+    Bilinear,
+}
+
+/// The source pixels a destination pixel samples along one axis, and the weight of `hi` out of
+/// 256.
+struct Sample {
+    lo: usize,
+    hi: usize,
+    weight: u32,
+}
+
+/// Computes the samples of the `visible` part of a `dst` span, scaled from a `src` span. Spans are
+/// given as a start and a length.
+fn samples(
+    src: (i32, u32),
+    dst: (i32, u32),
+    visible: (i32, u32),
+    filter: ScaleFilter,
+) -> Vec<Sample> {
+    let (src_start, src_len) = (src.0 as usize, i128::from(src.1));
+    let dst_len = i128::from(dst.1);
+    (0..visible.1)
+        .map(|i| {
+            // Twice the position of the destination pixel's center, relative to the span.
+            let center = 2 * (i128::from(visible.0) - i128::from(dst.0) + i128::from(i)) + 1;
+            let (lo, weight) = match filter {
+                ScaleFilter::Nearest => (center * src_len / (2 * dst_len), 0),
+                ScaleFilter::Bilinear => {
+                    // The source position of the center, in 256ths of a pixel, relative to the
+                    // center of the first source pixel.
+                    let pos = (center * src_len * 128 / dst_len - 128).max(0);
+                    (pos >> 8, (pos & 255) as u32)
+                }
+            };
+            let lo = (lo as usize).min(src.1 as usize - 1);
+            let hi = (lo + 1).min(src.1 as usize - 1);
+            Sample {
+                lo: src_start + lo,
+                hi: src_start + hi,
+                weight,
+            }
+        })
+        .collect()
+}
+
+/// Blends `a` and `b`, with `b` weighted by `weight` out of 256. The result is scaled up by 256.
+fn lerp(a: u32, b: u32, weight: u32) -> u32 {
+    a * (256 - weight) + b * weight
+}
+
+/// Scales the `src_rect` part of `src` to fit `dst_rect`, and writes the `visible` part of the
+/// result into `dst`, which must have the same format as `src` and the same size as `visible`.
+///
+/// `src_rect` must lie within `src`, and `visible` within `dst_rect`. Neither may be empty.
+pub(crate) fn scale(
+    src: &Backend,
+    src_rect: Rect,
+    dst: &mut Backend,
+    dst_rect: Rect,
+    visible: Rect,
+    filter: ScaleFilter,
+) {
+    let bytes_per_pixel = src.bytes_per_pixel();
+    let columns = samples(
+        (src_rect.x, src_rect.width),
+        (dst_rect.x, dst_rect.width),
+        (visible.x, visible.width),
+        filter,
+    );
+    let rows = samples(
+        (src_rect.y, src_rect.height),
+        (dst_rect.y, dst_rect.height),
+        (visible.y, visible.height),
+        filter,
+    );
+
+    for (row, sample) in dst.rows_mut().zip(&rows) {
+        let top = src.row(sample.lo as u32).unwrap();
+        let bottom = src.row(sample.hi as u32).unwrap();
+        for (pixel, column) in row.chunks_exact_mut(bytes_per_pixel).zip(&columns) {
+            let (left, right) = (column.lo * bytes_per_pixel, column.hi * bytes_per_pixel);
+            for (c, channel) in pixel.iter_mut().enumerate() {
+                let [tl, tr, bl, br] = [
+                    top[left + c],
+                    top[right + c],
+                    bottom[left + c],
+                    bottom[right + c],
+                ]
+                .map(u32::from);
+                let value = lerp(
+                    lerp(tl, tr, column.weight),
+                    lerp(bl, br, column.weight),
+                    sample.weight,
+                );
+                *channel = ((value + (1 << 15)) >> 16) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlitError, HeadlessWindow, BGR};
+
+    #[test]
+    fn pixelbuffer_blit_scaled() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGR>((4, 3), (3, 2));
+        pb.row_mut(1).unwrap()[1..].copy_from_slice(&[BGR::new(0, 0, 0), BGR::new(255, 255, 255)]);
+
+        let src = Rect::new(1, 1, 2, 1);
+        pb.blit_scaled(src, Rect::new(0, 0, 4, 1), ScaleFilter::Bilinear, &window)
+            .unwrap();
+        assert_eq!(vec![0, 64, 191, 255], window.first_bytes(0));
+
+        // The destination gets clipped to the window.
+        pb.blit_scaled(src, Rect::new(-2, 1, 8, 4), ScaleFilter::Nearest, &window)
+            .unwrap();
+        assert_eq!(vec![0, 0, 255, 255], window.first_bytes(2));
+
+        let result = pb.blit_scaled(
+            Rect::new(2, 1, 2, 1),
+            Rect::from_size(4, 4),
+            ScaleFilter::Nearest,
+            &window,
+        );
+        assert!(matches!(result, Err(BlitError::OutOfBounds)));
+    }
+}
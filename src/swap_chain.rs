@@ -0,0 +1,175 @@
+use crate::{BlitError, BlitTarget, PixelBufferFormat, PixelBufferTyped, ResizeContents};
+
+/// A set of pixel buffers that take turns being drawn into and presented.
+///
+/// Drawing always happens in the back buffer returned by [`acquire`](Self::acquire). Presenting
+/// blits the back buffer onto the window and moves on to the next buffer, so that the pixels of
+/// the frame that was just presented are left alone while the next one gets drawn.
+///
+/// Each buffer keeps track of its age: how many presents ago its contents were presented, with
+/// `0` meaning that its contents are undefined. This follows the semantics of
+/// `EGL_EXT_buffer_age`, and allows redrawing only the parts of the frame that changed since the
+/// back buffer was last presented.
+pub struct SwapChain<P: PixelBufferFormat> {
+    buffers: Vec<PixelBufferTyped<P>>,
+    /// The value of `presents` right after each buffer in `buffers` was last presented.
+    presented_at: Vec<u64>,
+    /// The number of presents made by the swap chain.
+    presents: u64,
+    /// The index of the back buffer.
+    back: usize,
+}
+
+impl<P: PixelBufferFormat> SwapChain<P> {
+    /// Creates a swap chain of `buffer_count` pixel buffers for `window`, all with undefined
+    /// contents.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::new`].
+    ///
+    /// # Panics
+    /// Panics if `buffer_count` is zero.
+    pub fn new<H: BlitTarget>(
+        width: u32,
+        height: u32,
+        buffer_count: usize,
+        window: &H,
+    ) -> Result<SwapChain<P>, BlitError> {
+        assert_ne!(0, buffer_count, "a swap chain needs at least one buffer");
+        let buffers = (0..buffer_count)
+            .map(|_| PixelBufferTyped::new(width, height, window))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SwapChain {
+            presented_at: vec![0; buffer_count],
+            buffers,
+            presents: 0,
+            back: 0,
+        })
+    }
+
+    /// The number of buffers in the swap chain.
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// The width, in pixels, of the buffers.
+    pub fn width(&self) -> u32 {
+        self.buffers[0].width()
+    }
+
+    /// The height, in pixels, of the buffers.
+    pub fn height(&self) -> u32 {
+        self.buffers[0].height()
+    }
+
+    /// The back buffer, which the next frame should be drawn into.
+    pub fn acquire(&mut self) -> &mut PixelBufferTyped<P> {
+        &mut self.buffers[self.back]
+    }
+
+    /// The age of the back buffer's contents.
+    ///
+    /// `0` means the contents are undefined, and the whole frame must be drawn. Otherwise, the
+    /// back buffer holds the frame that was presented `age` presents ago.
+    pub fn buffer_age(&self) -> u32 {
+        match self.buffers[self.back].buffer_age() {
+            0 => 0,
+            _ => (self.presents - self.presented_at[self.back] + 1).min(u64::from(u32::MAX)) as u32,
+        }
+    }
+
+    /// Blits the back buffer onto `window`, then moves on to the next buffer.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::blit`]. The back buffer stays the same if
+    /// the blit fails.
+    pub fn present<H: BlitTarget>(&mut self, window: &H) -> Result<(), BlitError> {
+        self.buffers[self.back].blit(window)?;
+        self.presents += 1;
+        self.presented_at[self.back] = self.presents;
+        self.back = (self.back + 1) % self.buffers.len();
+        Ok(())
+    }
+
+    /// Resizes every buffer, leaving their contents undefined.
+    ///
+    /// Buffers that already have the new size are left alone, so this can be called before
+    /// drawing every frame without losing the buffers' ages.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::resize`]. The buffers that got resized
+    /// before the error keep their new size, and calling `resize` again resizes the rest.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), BlitError> {
+        for buffer in &mut self.buffers {
+            if (buffer.width(), buffer.height()) != (width, height) {
+                buffer.resize(width, height, ResizeContents::Undefined)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadlessWindow, PixelBufferFormatType, BGRA};
+
+    #[test]
+    fn swap_chain_rotates_and_ages() {
+        let window = HeadlessWindow::new(2, 2, PixelBufferFormatType::BGRA);
+        let mut chain = SwapChain::<BGRA>::new(2, 2, 3, &window).unwrap();
+        let mut ages = Vec::new();
+        for frame in 1..=5 {
+            ages.push(chain.buffer_age());
+            chain.acquire().row_mut(0).unwrap()[0] = BGRA::new(frame, 0, 0, 0);
+            chain.present(&window).unwrap();
+            assert_eq!(frame, window.row(0).unwrap()[0]);
+        }
+        assert_eq!(vec![0, 0, 0, 3, 3], ages);
+        // The back buffer holds the frame presented three presents ago.
+        assert_eq!(BGRA::new(3, 0, 0, 0), chain.acquire().row(0).unwrap()[0]);
+
+        chain.resize(2, 2).unwrap();
+        assert_eq!(3, chain.buffer_age());
+        chain.resize(3, 2).unwrap();
+        assert_eq!(0, chain.buffer_age());
+        assert_eq!(3, chain.acquire().width());
+
+        // Every buffer gets checked, not just the first one.
+        chain.present(&window).unwrap();
+        chain
+            .acquire()
+            .resize(1, 1, ResizeContents::Undefined)
+            .unwrap();
+        chain.resize(3, 2).unwrap();
+        assert_eq!((3, 2), (chain.acquire().width(), chain.acquire().height()));
+    }
+
+    #[test]
+    fn pixelbuffer_buffer_age() {
+        let (window, mut pb) = HeadlessWindow::with_buffer::<BGRA>((4, 4), (4, 4));
+        let other = HeadlessWindow::new(4, 4, PixelBufferFormatType::BGRA);
+        assert_eq!(0, pb.buffer_age());
+
+        // Failed blits don't count as presents.
+        assert!(pb.blit(&other).is_err());
+        assert_eq!(0, pb.buffer_age());
+
+        pb.blit_rect((1, 1), (0, 0), (2, 2), &window).unwrap();
+        assert_eq!(1, pb.buffer_age());
+        pb.blit(&window).unwrap();
+        assert_eq!(1, pb.buffer_age());
+
+        pb.resize(4, 4, ResizeContents::Preserve).unwrap();
+        assert_eq!(0, pb.buffer_age());
+        pb.set_damage_tracking(true);
+        pb.blit_damaged(&window).unwrap();
+        assert_eq!(1, pb.buffer_age());
+
+        // The window shows the slice's image instead of the buffer's contents afterwards.
+        let image = [BGRA::new(0, 0, 0, 0); 4 * 4];
+        pb.blit_from_slice(&window, &image, 4, 4, 4).unwrap();
+        assert_eq!(0, pb.buffer_age());
+        assert_eq!(&[crate::Rect::from_size(4, 4)][..], pb.damage());
+    }
+}
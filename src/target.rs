@@ -0,0 +1,86 @@
+use crate::{memory, HeadlessWindow, PixelBufferFormatType};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use std::{
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Something that pixel buffers can be blitted onto.
+///
+/// This is implemented for every window that implements [`HasRawWindowHandle`], as well as for
+/// the virtual windows provided by this crate, such as [`HeadlessWindow`].
+pub trait BlitTarget: private::Sealed {}
+
+/// The concrete kind of a [`BlitTarget`].
+#[derive(Clone, Copy)]
+pub enum Target<'a> {
+    Window(RawWindowHandle),
+    Memory(&'a dyn MemoryTarget),
+}
+
+/// A target that's presented to by copying out of an in-memory pixel buffer.
+pub trait MemoryTarget {
+    /// A unique ID for the target, used to detect buffers blitted onto the wrong target.
+    fn id(&self) -> u64;
+
+    /// The only format buffers for this target may use.
+    fn format(&self) -> PixelBufferFormatType;
+
+    /// The width and height of the target, which blits get clipped to.
+    fn size(&self) -> (u32, u32);
+
+    /// Presents the `size` pixels at `src_pos` in `src` at `dst_pos` on the target.
+    fn present(
+        &self,
+        src: &memory::PixelBuffer,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<()>;
+}
+
+/// Allocates a new, unique ID for a `MemoryTarget`.
+pub(crate) fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub mod private {
+    pub trait Sealed {
+        fn target(&self) -> super::Target<'_>;
+    }
+}
+
+impl<H: HasRawWindowHandle> private::Sealed for H {
+    fn target(&self) -> Target<'_> {
+        Target::Window(self.raw_window_handle())
+    }
+}
+impl<H: HasRawWindowHandle> BlitTarget for H {}
+
+macro_rules! memory_target {
+    ($($(#[$attr:meta])* $ty:ty),+ $(,)?) => {$(
+        $(#[$attr])*
+        impl private::Sealed for $ty {
+            fn target(&self) -> Target<'_> {
+                Target::Memory(self)
+            }
+        }
+        $(#[$attr])*
+        impl BlitTarget for $ty {}
+    )+};
+}
+
+memory_target!(
+    HeadlessWindow,
+    crate::VncServer,
+    #[cfg(target_os = "linux")]
+    crate::Framebuffer,
+);
+
+impl<W: io::Write> private::Sealed for crate::Terminal<W> {
+    fn target(&self) -> Target<'_> {
+        Target::Memory(self)
+    }
+}
+impl<W: io::Write> BlitTarget for crate::Terminal<W> {}
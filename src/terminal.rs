@@ -0,0 +1,401 @@
+use crate::{
+    memory::{self, Layout},
+    target::{self, MemoryTarget},
+    PixelBufferFormatType,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+/// The escape sequences a [`Terminal`] draws pixels with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TerminalMode {
+    /// The [kitty graphics protocol](https://sw.kovidgoyal.net/kitty/graphics-protocol/).
+    ///
+    /// The whole surface is transmitted on the first blit, and every later blit only updates the
+    /// blitted rectangle of the image.
+    Kitty,
+    /// Sixel graphics, quantized to a 6x6x6 color cube.
+    ///
+    /// Sixel images can't be partially updated, so every blit redraws the whole surface.
+    Sixel,
+    /// Unicode upper half blocks (`▀`) colored with 24-bit ANSI escape codes.
+    ///
+    /// Each character cell holds two vertically stacked pixels, so this works in nearly any
+    /// modern terminal, at a fairly low resolution.
+    HalfBlock,
+}
+
+/// A virtual window that draws onto a terminal, by writing escape sequences to an
+/// [`io::Write`].
+///
+/// Like [`HeadlessWindow`](crate::HeadlessWindow), a terminal keeps a surface in memory that
+/// pixel buffers get blitted onto. After each blit, the changed part of the surface is written to
+/// the output, drawn with the top-left corner of the terminal as the surface's origin.
+///
+/// The surface has the format passed to [`new`](Self::new), which pixel buffers blitted onto it
+/// must share. Creating a pixel buffer of any other format fails with
+/// [`BlitError::FormatNotSupported`](crate::BlitError::FormatNotSupported). Any alpha channel is
+/// ignored.
+pub struct Terminal<W: Write> {
+    id: u64,
+    mode: TerminalMode,
+    format: PixelBufferFormatType,
+    layout: Layout,
+    surface: RefCell<Vec<u8>>,
+    output: RefCell<W>,
+    /// Whether the kitty image has been transmitted, and can be updated in place.
+    transmitted: Cell<bool>,
+}
+
+impl<W: Write> Terminal<W> {
+    /// Creates a new terminal window with a surface of the given size and format, drawing onto
+    /// `output`.
+    ///
+    /// Nothing gets written until the first blit. The surface's pixels are all initialized to
+    /// black.
+    pub fn new(
+        output: W,
+        width: u32,
+        height: u32,
+        format: PixelBufferFormatType,
+        mode: TerminalMode,
+    ) -> Terminal<W> {
+        let layout = Layout::new(width, height, format);
+        Terminal {
+            id: target::next_id(),
+            mode,
+            format,
+            layout,
+            surface: RefCell::new(vec![0; layout.len()]),
+            output: RefCell::new(output),
+            transmitted: Cell::new(false),
+        }
+    }
+
+    /// The width, in pixels, of the terminal's surface.
+    pub fn width(&self) -> u32 {
+        self.layout.width
+    }
+
+    /// The height, in pixels, of the terminal's surface.
+    pub fn height(&self) -> u32 {
+        self.layout.height
+    }
+
+    /// The format of the terminal's surface.
+    pub fn format(&self) -> PixelBufferFormatType {
+        self.format
+    }
+
+    /// The escape sequences the terminal draws pixels with.
+    pub fn mode(&self) -> TerminalMode {
+        self.mode
+    }
+
+    /// Gets a reference to the output.
+    pub fn get_ref(&self) -> std::cell::Ref<'_, W> {
+        self.output.borrow()
+    }
+
+    /// Consumes the terminal, returning the output.
+    pub fn into_inner(self) -> W {
+        self.output.into_inner()
+    }
+
+    /// The red, green and blue channels of the pixel at `(x, y)` on the surface.
+    fn rgb(&self, surface: &[u8], x: u32, y: u32) -> (u8, u8, u8) {
+        let i = y as usize * self.layout.stride + x as usize * self.layout.bytes_per_pixel;
+        memory::rgb(self.format, &surface[i..])
+    }
+
+    /// The RGB bytes of the given rectangle of the surface.
+    fn rgb_bytes(&self, surface: &[u8], pos: (u32, u32), size: (u32, u32)) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(size.0 as usize * size.1 as usize * 3);
+        for y in pos.1..pos.1 + size.1 {
+            for x in pos.0..pos.0 + size.0 {
+                let (r, g, b) = self.rgb(surface, x, y);
+                bytes.extend_from_slice(&[r, g, b]);
+            }
+        }
+        bytes
+    }
+
+    fn draw_kitty(
+        &self,
+        out: &mut String,
+        surface: &[u8],
+        mut pos: (u32, u32),
+        mut size: (u32, u32),
+    ) {
+        // Kitty image IDs are non-zero 32-bit integers.
+        let image_id = (self.id % u64::from(u32::MAX)) + 1;
+        let control = if self.transmitted.replace(true) {
+            format!(
+                "a=f,r=1,i={},x={},y={},s={},v={},f=24,q=2",
+                image_id, pos.0, pos.1, size.0, size.1
+            )
+        } else {
+            // The first transmission has to carry the whole image.
+            out.push_str("\x1b[H");
+            (pos, size) = ((0, 0), (self.layout.width, self.layout.height));
+            format!("a=T,i={},s={},v={},f=24,C=1,q=2", image_id, size.0, size.1)
+        };
+
+        let payload = base64(&self.rgb_bytes(surface, pos, size));
+        let mut chunks = payload.as_bytes().chunks(4096).peekable();
+        let mut first = true;
+        while let Some(chunk) = chunks.next() {
+            let more = chunks.peek().is_some() as u8;
+            match first {
+                true => write!(out, "\x1b_G{},m={};", control, more),
+                false => write!(out, "\x1b_Gm={};", more),
+            }
+            .unwrap();
+            out.push_str(std::str::from_utf8(chunk).unwrap());
+            out.push_str("\x1b\\");
+            first = false;
+        }
+    }
+
+    fn draw_sixel(&self, out: &mut String, surface: &[u8]) {
+        let (width, height) = (self.layout.width, self.layout.height);
+        // Quantize every pixel to a register in a 6x6x6 color cube.
+        let level = |c: u8| (u32::from(c) * 5 + 127) / 255;
+        let registers: Vec<u32> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let (r, g, b) = self.rgb(surface, x, y);
+                level(r) * 36 + level(g) * 6 + level(b)
+            })
+            .collect();
+
+        write!(out, "\x1b[H\x1bPq\"1;1;{};{}", width, height).unwrap();
+        for register in registers.iter().collect::<BTreeSet<_>>() {
+            let (r, g, b) = (register / 36, register / 6 % 6, register % 6);
+            write!(out, "#{};2;{};{};{}", register, r * 20, g * 20, b * 20).unwrap();
+        }
+
+        for band in 0..height.div_ceil(6) {
+            let rows = band * 6..(band * 6 + 6).min(height);
+            let band_registers: BTreeSet<u32> = rows
+                .clone()
+                .flat_map(|y| &registers[(y * width) as usize..((y + 1) * width) as usize])
+                .copied()
+                .collect();
+            for (i, &register) in band_registers.iter().enumerate() {
+                if i != 0 {
+                    out.push('$');
+                }
+                write!(out, "#{}", register).unwrap();
+                let sixels = (0..width).map(|x| {
+                    let bits = rows
+                        .clone()
+                        .filter(|&y| registers[(y * width + x) as usize] == register)
+                        .fold(0, |bits, y| bits | 1 << (y - band * 6));
+                    (63 + bits) as u8 as char
+                });
+                push_sixels(out, sixels);
+            }
+            out.push('-');
+        }
+        out.push_str("\x1b\\");
+    }
+
+    fn draw_half_block(&self, out: &mut String, surface: &[u8], pos: (u32, u32), size: (u32, u32)) {
+        let mut fg = None;
+        let mut bg = None;
+        for cell_y in pos.1 / 2..(pos.1 + size.1).div_ceil(2) {
+            write!(out, "\x1b[{};{}H", cell_y + 1, pos.0 + 1).unwrap();
+            for x in pos.0..pos.0 + size.0 {
+                let top = self.rgb(surface, x, cell_y * 2);
+                let bottom = match cell_y * 2 + 1 < self.layout.height {
+                    true => Some(self.rgb(surface, x, cell_y * 2 + 1)),
+                    false => None,
+                };
+                if fg != Some(top) {
+                    write!(out, "\x1b[38;2;{};{};{}m", top.0, top.1, top.2).unwrap();
+                    fg = Some(top);
+                }
+                if bg != Some(bottom) {
+                    match bottom {
+                        Some((r, g, b)) => write!(out, "\x1b[48;2;{};{};{}m", r, g, b).unwrap(),
+                        None => out.push_str("\x1b[49m"),
+                    }
+                    bg = Some(bottom);
+                }
+                out.push('▀');
+            }
+        }
+        out.push_str("\x1b[0m");
+    }
+}
+
+/// Appends sixel characters, run-length encoding repeats and dropping trailing empty sixels.
+fn push_sixels(out: &mut String, sixels: impl Iterator<Item = char>) {
+    let sixels: Vec<char> = sixels.collect();
+    let end = sixels.iter().rposition(|&c| c != '?').map_or(0, |i| i + 1);
+    let mut i = 0;
+    while i < end {
+        let run = sixels[i..end]
+            .iter()
+            .take_while(|&&c| c == sixels[i])
+            .count();
+        match run {
+            1..=3 => (0..run).for_each(|_| out.push(sixels[i])),
+            _ => write!(out, "!{}{}", run, sixels[i]).unwrap(),
+        }
+        i += run;
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - i * 8));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - i * 6)) as usize & 63] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+impl<W: Write> MemoryTarget for Terminal<W> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn format(&self) -> PixelBufferFormatType {
+        self.format
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.layout.width, self.layout.height)
+    }
+
+    fn present(
+        &self,
+        src: &memory::PixelBuffer,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<()> {
+        let mut surface = self.surface.borrow_mut();
+        memory::copy_rect(
+            src.bytes(),
+            src.layout(),
+            src_pos,
+            &mut surface,
+            self.layout,
+            dst_pos,
+            size,
+        );
+
+        // Only draw the part of the surface that was actually blitted onto.
+        let size = (
+            size.0
+                .min(src.width().saturating_sub(src_pos.0))
+                .min(self.layout.width.saturating_sub(dst_pos.0)),
+            size.1
+                .min(src.height().saturating_sub(src_pos.1))
+                .min(self.layout.height.saturating_sub(dst_pos.1)),
+        );
+        if size.0 == 0 || size.1 == 0 {
+            return Ok(());
+        }
+
+        let mut out = String::new();
+        match self.mode {
+            TerminalMode::Kitty => self.draw_kitty(&mut out, &surface, dst_pos, size),
+            TerminalMode::Sixel => self.draw_sixel(&mut out, &surface),
+            TerminalMode::HalfBlock => self.draw_half_block(&mut out, &surface, dst_pos, size),
+        }
+        let mut output = self.output.borrow_mut();
+        output.write_all(out.as_bytes())?;
+        output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PixelBufferTyped, RGB};
+
+    fn blit(mode: TerminalMode, pixels: &[RGB], size: (u32, u32)) -> Terminal<Vec<u8>> {
+        let terminal = Terminal::new(Vec::new(), size.0, size.1, PixelBufferFormatType::RGB, mode);
+        let mut pb = PixelBufferTyped::<RGB>::new(size.0, size.1, &terminal).unwrap();
+        for (y, row) in pb.rows_mut().enumerate() {
+            row.copy_from_slice(&pixels[y * size.0 as usize..][..size.0 as usize]);
+        }
+        pb.blit(&terminal).unwrap();
+        terminal
+    }
+
+    #[test]
+    fn terminal_half_block() {
+        let red = RGB::from_rgb(255, 0, 0);
+        let blue = RGB::from_rgb(0, 0, 255);
+        let terminal = blit(
+            TerminalMode::HalfBlock,
+            &[red, blue, blue, blue, red, red],
+            (2, 3),
+        );
+        assert_eq!(
+            "\x1b[1;1H\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[38;2;0;0;255m▀\
+             \x1b[2;1H\x1b[38;2;255;0;0m\x1b[49m▀▀\x1b[0m",
+            std::str::from_utf8(&terminal.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn terminal_kitty() {
+        let terminal = blit(TerminalMode::Kitty, &[RGB::from_rgb(1, 2, 3)], (1, 1));
+        let id = terminal.id % u64::from(u32::MAX) + 1;
+
+        let mut pb = PixelBufferTyped::<RGB>::new(1, 1, &terminal).unwrap();
+        pb.row_mut(0).unwrap()[0] = RGB::from_rgb(255, 255, 255);
+        pb.blit(&terminal).unwrap();
+
+        assert_eq!(
+            format!(
+                "\x1b[H\x1b_Ga=T,i={0},s=1,v=1,f=24,C=1,q=2,m=0;AQID\x1b\\\
+                 \x1b_Ga=f,r=1,i={0},x=0,y=0,s=1,v=1,f=24,q=2,m=0;////\x1b\\",
+                id
+            ),
+            std::str::from_utf8(&terminal.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn terminal_sixel() {
+        let red = RGB::from_rgb(255, 0, 0);
+        let black = RGB::from_rgb(0, 0, 0);
+        let terminal = blit(
+            TerminalMode::Sixel,
+            &[red, red, red, red, black, red],
+            (6, 1),
+        );
+        assert_eq!(
+            "\x1b[H\x1bPq\"1;1;6;1#0;2;0;0;0#180;2;100;0;0#0!4?@$#180!4@?@-\x1b\\",
+            std::str::from_utf8(&terminal.into_inner()).unwrap()
+        );
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+    }
+}
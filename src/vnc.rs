@@ -0,0 +1,755 @@
+//! A minimal RFB (VNC) server, implementing version 3.8 of the protocol as described in
+//! [RFC 6143](https://www.rfc-editor.org/rfc/rfc6143).
+
+use crate::{
+    damage::Damage,
+    memory::{self, Layout},
+    rect::Rect,
+    target::{self, MemoryTarget},
+    PixelBufferFormatType,
+};
+use std::{
+    convert::TryInto,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_RRE: i32 = 2;
+
+/// A virtual window that serves its contents to VNC clients over TCP.
+///
+/// Like [`HeadlessWindow`](crate::HeadlessWindow), the server keeps a surface in memory that
+/// pixel buffers get blitted onto. Each client is sent the blitted rectangles in answer to its
+/// `FramebufferUpdateRequest`s, using RRE encoding for clients that support it and raw encoding
+/// otherwise. Rectangles blitted while a client has no request pending are merged into its next
+/// update, so slow clients skip frames instead of falling behind. Clients that connect later are
+/// sent the whole surface when they request it.
+///
+/// Blits never wait on the network: each client is written to from its own background thread.
+/// The server accepts any client without authentication, so it should only be bound to trusted
+/// networks. Clients are served on background threads, which stop once the server is dropped.
+pub struct VncServer {
+    id: u64,
+    format: PixelBufferFormatType,
+    layout: Layout,
+    local_addr: SocketAddr,
+    shared: Arc<Shared>,
+}
+
+/// The state shared with the background threads.
+struct Shared {
+    format: PixelBufferFormatType,
+    layout: Layout,
+    name: String,
+    surface: Mutex<Vec<u8>>,
+    clients: Mutex<Vec<Arc<Client>>>,
+    /// Every open connection, including those still in the handshake, by ID. Used to disconnect
+    /// them when the server gets dropped.
+    connections: Mutex<Vec<(u64, TcpStream)>>,
+    next_connection: AtomicU64,
+    closed: AtomicBool,
+}
+
+struct Client {
+    state: Mutex<ClientState>,
+    /// Wakes up the client's writer thread when there may be an update to send.
+    wake: Condvar,
+    /// The client's socket, for disconnecting it.
+    stream: TcpStream,
+}
+
+struct ClientState {
+    format: ClientFormat,
+    /// The parts of the surface the client hasn't been sent since they changed.
+    damage: Damage,
+    /// The region of the surface the client requested an update for, if it's waiting for one.
+    requested: Option<Rect>,
+    closed: bool,
+}
+
+/// An RFB `PIXEL_FORMAT`. Only true-color formats are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PixelFormat {
+    bits_per_pixel: u8,
+    depth: u8,
+    big_endian: bool,
+    max: [u16; 3],
+    shift: [u8; 3],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ClientFormat {
+    pixel_format: PixelFormat,
+    rre: bool,
+}
+
+impl PixelFormat {
+    /// 32-bit little-endian pixels, with each channel in its own byte, laid out like `format`.
+    fn native(format: PixelBufferFormatType) -> PixelFormat {
+        let shift = match format {
+            PixelBufferFormatType::BGR | PixelBufferFormatType::BGRA => [16, 8, 0],
+            PixelBufferFormatType::RGB | PixelBufferFormatType::RGBA => [0, 8, 16],
+        };
+        PixelFormat {
+            bits_per_pixel: 32,
+            depth: 24,
+            big_endian: false,
+            max: [255; 3],
+            shift,
+        }
+    }
+
+    fn parse(bytes: &[u8; 16]) -> io::Result<PixelFormat> {
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let format = PixelFormat {
+            bits_per_pixel: bytes[0],
+            depth: bytes[1],
+            big_endian: bytes[2] != 0,
+            max: [u16_at(4), u16_at(6), u16_at(8)],
+            shift: [bytes[10], bytes[11], bytes[12]],
+        };
+        let true_color = bytes[3] != 0;
+        if !true_color || ![8, 16, 32].contains(&format.bits_per_pixel) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unsupported RFB pixel format",
+            ));
+        }
+        Ok(format)
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.bits_per_pixel, self.depth, self.big_endian as u8, 1]);
+        for max in &self.max {
+            out.extend_from_slice(&max.to_be_bytes());
+        }
+        out.extend_from_slice(&self.shift);
+        out.extend_from_slice(&[0; 3]);
+    }
+
+    fn pixel(&self, (r, g, b): (u8, u8, u8)) -> u32 {
+        [r, g, b]
+            .iter()
+            .zip(&self.max)
+            .zip(&self.shift)
+            .fold(0, |pixel, ((&c, &max), &shift)| {
+                let c = (u32::from(c) * u32::from(max) + 127) / 255;
+                pixel | c.checked_shl(u32::from(shift)).unwrap_or(0)
+            })
+    }
+
+    fn write_pixel(&self, pixel: u32, out: &mut Vec<u8>) {
+        let len = self.bits_per_pixel as usize / 8;
+        match self.big_endian {
+            true => out.extend_from_slice(&pixel.to_be_bytes()[4 - len..]),
+            false => out.extend_from_slice(&pixel.to_le_bytes()[..len]),
+        }
+    }
+}
+
+impl VncServer {
+    /// Starts a VNC server on `addr`, serving a surface of the given size and format.
+    ///
+    /// The surface's pixels are all initialized to black. RFB limits framebuffers to 65535 pixels
+    /// in each dimension, so an error is returned for larger surfaces.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        width: u32,
+        height: u32,
+        format: PixelBufferFormatType,
+    ) -> io::Result<VncServer> {
+        if width > u32::from(u16::MAX) || height > u32::from(u16::MAX) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "VNC surfaces must be at most 65535 pixels wide and tall",
+            ));
+        }
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let layout = Layout::new(width, height, format);
+        let shared = Arc::new(Shared {
+            format,
+            layout,
+            name: "winit-blit".to_owned(),
+            surface: Mutex::new(vec![0; layout.len()]),
+            clients: Mutex::new(Vec::new()),
+            connections: Mutex::new(Vec::new()),
+            next_connection: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+        });
+
+        {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shared.closed.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let shared = shared.clone();
+                    if let Ok(stream) = stream {
+                        thread::spawn(move || {
+                            let _ = shared.serve(stream);
+                        });
+                    }
+                }
+            });
+        }
+
+        Ok(VncServer {
+            id: target::next_id(),
+            format,
+            layout,
+            local_addr,
+            shared,
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The width, in pixels, of the server's surface.
+    pub fn width(&self) -> u32 {
+        self.layout.width
+    }
+
+    /// The height, in pixels, of the server's surface.
+    pub fn height(&self) -> u32 {
+        self.layout.height
+    }
+
+    /// The format of the server's surface.
+    pub fn format(&self) -> PixelBufferFormatType {
+        self.format
+    }
+
+    /// The number of clients that have finished connecting and are being sent updates.
+    pub fn client_count(&self) -> usize {
+        self.shared.clients.lock().unwrap().len()
+    }
+}
+
+/// The parts of `rect` outside of `hole`, as up to four rectangles.
+fn subtract(rect: Rect, hole: Rect) -> Vec<Rect> {
+    let hole = match rect.intersection(&hole) {
+        Some(hole) => hole,
+        None => return vec![rect],
+    };
+    let (hole_right, hole_bottom) = (hole.right() as i32, hole.bottom() as i32);
+    [
+        Rect::new(rect.x, rect.y, rect.width, (hole.y - rect.y) as u32),
+        Rect::new(
+            rect.x,
+            hole_bottom,
+            rect.width,
+            (rect.bottom() - hole.bottom()) as u32,
+        ),
+        Rect::new(rect.x, hole.y, (hole.x - rect.x) as u32, hole.height),
+        Rect::new(
+            hole_right,
+            hole.y,
+            (rect.right() - hole.right()) as u32,
+            hole.height,
+        ),
+    ]
+    .iter()
+    .filter(|rect| !rect.is_empty())
+    .copied()
+    .collect()
+}
+
+impl ClientState {
+    /// Takes the rectangles to send in answer to the pending request, if any of them changed.
+    fn take_update(&mut self) -> Option<Vec<Rect>> {
+        let requested = self.requested?;
+        let rects: Vec<Rect> = self
+            .damage
+            .rects()
+            .iter()
+            .filter_map(|rect| rect.intersection(&requested))
+            .collect();
+        if rects.is_empty() {
+            return None;
+        }
+        // Changes outside of the requested region wait for a request that covers them.
+        let remaining: Vec<Rect> = self
+            .damage
+            .rects()
+            .iter()
+            .flat_map(|rect| subtract(*rect, requested))
+            .collect();
+        self.damage.clear();
+        for rect in remaining {
+            self.damage.add(rect);
+        }
+        self.requested = None;
+        Some(rects)
+    }
+}
+
+impl Client {
+    /// Disconnects the client, stopping its threads.
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.wake.notify_one();
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl Shared {
+    /// Runs a client's connection, keeping track of it until it ends.
+    fn serve(self: Arc<Self>, stream: TcpStream) -> io::Result<()> {
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        self.connections
+            .lock()
+            .unwrap()
+            .push((id, stream.try_clone()?));
+        // The server may have been dropped before the connection got registered, in which case
+        // nothing is going to disconnect it.
+        let result = match self.closed.load(Ordering::SeqCst) {
+            true => Ok(()),
+            false => self.serve_client(stream),
+        };
+        self.connections.lock().unwrap().retain(|(i, _)| *i != id);
+        result
+    }
+
+    /// Runs a client's connection, from the handshake until it disconnects.
+    fn serve_client(self: &Arc<Self>, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        stream.write_all(b"RFB 003.008\n")?;
+        let mut version = [0; 12];
+        stream.read_exact(&mut version)?;
+        let minor = match &version {
+            b"RFB 003.003\n" => 3,
+            b"RFB 003.007\n" => 7,
+            _ if version.starts_with(b"RFB 003.") => 8,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Not an RFB client",
+                ))
+            }
+        };
+
+        // Only the "None" security type is offered.
+        if minor == 3 {
+            stream.write_all(&1u32.to_be_bytes())?;
+        } else {
+            stream.write_all(&[1, 1])?;
+            let mut security = [0];
+            stream.read_exact(&mut security)?;
+            if minor == 8 {
+                stream.write_all(&0u32.to_be_bytes())?;
+            }
+        }
+        // `ClientInit`. Sharing is always allowed, so the flag doesn't matter.
+        stream.read_exact(&mut [0])?;
+
+        let pixel_format = PixelFormat::native(self.format);
+        let mut init = Vec::new();
+        init.extend_from_slice(&(self.layout.width as u16).to_be_bytes());
+        init.extend_from_slice(&(self.layout.height as u16).to_be_bytes());
+        pixel_format.write(&mut init);
+        init.extend_from_slice(&(self.name.len() as u32).to_be_bytes());
+        init.extend_from_slice(self.name.as_bytes());
+        stream.write_all(&init)?;
+
+        let client = Arc::new(Client {
+            state: Mutex::new(ClientState {
+                format: ClientFormat {
+                    pixel_format,
+                    rre: false,
+                },
+                damage: Damage::default(),
+                requested: None,
+                closed: false,
+            }),
+            wake: Condvar::new(),
+            stream: stream.try_clone()?,
+        });
+        {
+            let (shared, client, stream) = (self.clone(), client.clone(), stream.try_clone()?);
+            thread::spawn(move || {
+                if shared.write_updates(&client, stream).is_err() {
+                    client.close();
+                }
+            });
+        }
+        self.clients.lock().unwrap().push(client.clone());
+
+        let result = self.read_messages(&mut stream, &client);
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|c| !Arc::ptr_eq(c, &client));
+        client.close();
+        result
+    }
+
+    /// Sends a client updates as it requests them, until it gets closed.
+    fn write_updates(&self, client: &Client, mut stream: TcpStream) -> io::Result<()> {
+        loop {
+            let (format, rects) = {
+                let mut state = client.state.lock().unwrap();
+                loop {
+                    if state.closed {
+                        return Ok(());
+                    }
+                    if let Some(rects) = state.take_update() {
+                        break (state.format, rects);
+                    }
+                    state = client.wake.wait(state).unwrap();
+                }
+            };
+            let update = self.encode_update(&self.surface.lock().unwrap(), format, &rects);
+            stream.write_all(&update)?;
+        }
+    }
+
+    fn read_messages(&self, stream: &mut TcpStream, client: &Client) -> io::Result<()> {
+        loop {
+            let mut message_type = [0];
+            stream.read_exact(&mut message_type)?;
+            match message_type[0] {
+                // SetPixelFormat
+                0 => {
+                    let mut message = [0; 19];
+                    stream.read_exact(&mut message)?;
+                    let format = PixelFormat::parse(message[3..].try_into().unwrap())?;
+                    client.state.lock().unwrap().format.pixel_format = format;
+                }
+                // SetEncodings
+                2 => {
+                    let mut header = [0; 3];
+                    stream.read_exact(&mut header)?;
+                    let mut encodings =
+                        vec![0; u16::from_be_bytes([header[1], header[2]]) as usize * 4];
+                    stream.read_exact(&mut encodings)?;
+                    // Use RRE if the client prefers it to raw encoding.
+                    let rre = encodings
+                        .chunks(4)
+                        .map(|e| i32::from_be_bytes(e.try_into().unwrap()))
+                        .find(|&e| e == ENCODING_RAW || e == ENCODING_RRE)
+                        == Some(ENCODING_RRE);
+                    client.state.lock().unwrap().format.rre = rre;
+                }
+                // FramebufferUpdateRequest
+                3 => {
+                    let mut message = [0; 9];
+                    stream.read_exact(&mut message)?;
+                    let u16_at = |i: usize| u16::from_be_bytes([message[i], message[i + 1]]);
+                    let rect = Rect::new(
+                        i32::from(u16_at(1)),
+                        i32::from(u16_at(3)),
+                        u32::from(u16_at(5)),
+                        u32::from(u16_at(7)),
+                    );
+                    let bounds = Rect::from_size(self.layout.width, self.layout.height);
+                    if let Some(rect) = rect.intersection(&bounds) {
+                        let mut state = client.state.lock().unwrap();
+                        // Incremental requests only get answered once part of the region
+                        // changes. Otherwise, the whole region gets sent right away.
+                        let incremental = message[0] != 0;
+                        if !incremental {
+                            state.damage.add(rect);
+                        }
+                        state.requested = Some(match state.requested {
+                            Some(requested) => requested.union(&rect),
+                            None => rect,
+                        });
+                        client.wake.notify_one();
+                    }
+                }
+                // KeyEvent and PointerEvent are ignored.
+                4 => stream.read_exact(&mut [0; 7])?,
+                5 => stream.read_exact(&mut [0; 5])?,
+                // ClientCutText
+                6 => {
+                    let mut header = [0; 7];
+                    stream.read_exact(&mut header)?;
+                    let len = u32::from_be_bytes(header[3..].try_into().unwrap());
+                    io::copy(&mut (&mut *stream).take(u64::from(len)), &mut io::sink())?;
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unknown RFB client message",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Encodes a `FramebufferUpdate` of the given rectangles of the surface, which must lie within
+    /// it.
+    fn encode_update(&self, surface: &[u8], format: ClientFormat, rects: &[Rect]) -> Vec<u8> {
+        let mut update = vec![0, 0];
+        update.extend_from_slice(&(rects.len() as u16).to_be_bytes());
+        for rect in rects {
+            let pos = (rect.x as u32, rect.y as u32);
+            self.encode_rect(surface, format, pos, (rect.width, rect.height), &mut update);
+        }
+        update
+    }
+
+    /// Encodes a single rectangle of a `FramebufferUpdate`, including its header.
+    fn encode_rect(
+        &self,
+        surface: &[u8],
+        format: ClientFormat,
+        pos: (u32, u32),
+        size: (u32, u32),
+        update: &mut Vec<u8>,
+    ) {
+        let pixels: Vec<u32> = (pos.1..pos.1 + size.1)
+            .flat_map(|y| (pos.0..pos.0 + size.0).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let i = y as usize * self.layout.stride + x as usize * self.layout.bytes_per_pixel;
+                format
+                    .pixel_format
+                    .pixel(memory::rgb(self.format, &surface[i..]))
+            })
+            .collect();
+
+        for &n in &[pos.0, pos.1, size.0, size.1] {
+            update.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        let raw_len = pixels.len() * format.pixel_format.bits_per_pixel as usize / 8;
+        let rre = match format.rre {
+            true => encode_rre(&pixels, size.0, &format.pixel_format),
+            false => None,
+        };
+        match rre {
+            Some(rre) if rre.len() < raw_len => {
+                update.extend_from_slice(&ENCODING_RRE.to_be_bytes());
+                update.extend_from_slice(&rre);
+            }
+            _ => {
+                update.extend_from_slice(&ENCODING_RAW.to_be_bytes());
+                for &pixel in &pixels {
+                    format.pixel_format.write_pixel(pixel, update);
+                }
+            }
+        }
+    }
+}
+
+/// Encodes a rectangle of pixels with RRE, using runs of non-background pixels within each row
+/// as the subrectangles. Returns `None` if the rectangle is empty.
+fn encode_rre(pixels: &[u32], width: u32, format: &PixelFormat) -> Option<Vec<u8>> {
+    let background = *pixels.first()?;
+    let mut subrects = Vec::new();
+    let mut count = 0u32;
+    for (y, row) in pixels.chunks(width as usize).enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            let run = row[x..].iter().take_while(|&&p| p == row[x]).count();
+            if row[x] != background {
+                format.write_pixel(row[x], &mut subrects);
+                for &n in &[x, y, run, 1] {
+                    subrects.extend_from_slice(&(n as u16).to_be_bytes());
+                }
+                count += 1;
+            }
+            x += run;
+        }
+    }
+
+    let mut rre = count.to_be_bytes().to_vec();
+    format.write_pixel(background, &mut rre);
+    rre.extend_from_slice(&subrects);
+    Some(rre)
+}
+
+impl MemoryTarget for VncServer {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn format(&self) -> PixelBufferFormatType {
+        self.format
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.layout.width, self.layout.height)
+    }
+
+    fn present(
+        &self,
+        src: &memory::PixelBuffer,
+        src_pos: (u32, u32),
+        dst_pos: (u32, u32),
+        size: (u32, u32),
+    ) -> io::Result<()> {
+        let mut surface = self.shared.surface.lock().unwrap();
+        memory::copy_rect(
+            src.bytes(),
+            src.layout(),
+            src_pos,
+            &mut surface,
+            self.layout,
+            dst_pos,
+            size,
+        );
+        let size = (
+            size.0.min(src.width().saturating_sub(src_pos.0)),
+            size.1.min(src.height().saturating_sub(src_pos.1)),
+        );
+        drop(surface);
+
+        // The surface is at most 65535 pixels in each dimension, so the casts can't overflow.
+        let bounds = Rect::from_size(self.layout.width, self.layout.height);
+        let rect = Rect::new(dst_pos.0 as i32, dst_pos.1 as i32, size.0, size.1);
+        if let Some(rect) = rect.intersection(&bounds) {
+            for client in self.shared.clients.lock().unwrap().iter() {
+                client.state.lock().unwrap().damage.add(rect);
+                client.wake.notify_one();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VncServer {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        // Wake up the listener thread, so that it notices the server is closed.
+        let _ = TcpStream::connect(self.local_addr);
+        // Connections still in the handshake aren't clients yet, but would otherwise wait for the
+        // client forever.
+        for (_, stream) in self.shared.connections.lock().unwrap().iter() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        for client in self.shared.clients.lock().unwrap().iter() {
+            client.close();
+        }
+    }
+}
+
+/// Test helpers, shared with the conformance tests.
+#[cfg(test)]
+impl VncServer {
+    /// Connects to the server as an RFB 3.8 client, returning the stream and the `ServerInit`
+    /// message.
+    pub(crate) fn connect_client(&self) -> (TcpStream, Vec<u8>) {
+        let mut stream = TcpStream::connect(self.local_addr()).unwrap();
+        let mut version = [0; 12];
+        stream.read_exact(&mut version).unwrap();
+        assert_eq!(b"RFB 003.008\n", &version);
+        stream.write_all(b"RFB 003.008\n").unwrap();
+
+        let mut security = [0; 2];
+        stream.read_exact(&mut security).unwrap();
+        assert_eq!([1, 1], security);
+        stream.write_all(&[1]).unwrap();
+        let mut result = [0; 4];
+        stream.read_exact(&mut result).unwrap();
+        assert_eq!([0; 4], result);
+
+        stream.write_all(&[1]).unwrap();
+        let mut init = vec![0; 24 + "winit-blit".len()];
+        stream.read_exact(&mut init).unwrap();
+        (stream, init)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PixelBufferTyped, BGRA};
+
+    fn read_update(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut update = vec![0; len];
+        stream.read_exact(&mut update).unwrap();
+        update
+    }
+
+    #[test]
+    fn vnc_raw_update() {
+        let server = VncServer::bind("127.0.0.1:0", 4, 3, PixelBufferFormatType::BGRA).unwrap();
+        let (mut stream, init) = server.connect_client();
+        assert_eq!(
+            &[0, 4, 0, 3, 32, 24, 0, 1, 0, 255, 0, 255, 0, 255, 16, 8, 0][..],
+            &init[..17]
+        );
+
+        // Request the whole framebuffer, which is initially black.
+        stream.write_all(&[3, 0, 0, 0, 0, 0, 0, 4, 0, 3]).unwrap();
+        let update = read_update(&mut stream, 16 + 4 * 3 * 4);
+        assert_eq!(
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 4, 0, 3, 0, 0, 0, 0][..],
+            &update[..16]
+        );
+        assert!(update[16..].iter().all(|&b| b == 0));
+
+        // Blits made without a pending request are merged into the next update.
+        let mut pb = PixelBufferTyped::<BGRA>::new(2, 2, &server).unwrap();
+        pb.row_mut(1).unwrap()[1] = BGRA::new(1, 2, 3, 4);
+        pb.blit_rect((1, 1), (2, 0), (1, 1), &server).unwrap();
+        pb.blit_rect((1, 1), (3, 0), (1, 1), &server).unwrap();
+        stream.write_all(&[3, 1, 0, 0, 0, 0, 0, 4, 0, 3]).unwrap();
+        assert_eq!(
+            vec![0, 0, 0, 1, 0, 2, 0, 0, 0, 2, 0, 1, 0, 0, 0, 0, 1, 2, 3, 0, 1, 2, 3, 0],
+            read_update(&mut stream, 24)
+        );
+    }
+
+    #[test]
+    fn vnc_rre_update() {
+        let server = VncServer::bind("127.0.0.1:0", 4, 3, PixelBufferFormatType::BGRA).unwrap();
+        let (mut stream, _) = server.connect_client();
+
+        // Prefer RRE, and switch to 16-bit big-endian RGB565 pixels.
+        stream
+            .write_all(&[
+                0, 0, 0, 0, 16, 16, 1, 1, 0, 31, 0, 63, 0, 31, 11, 5, 0, 0, 0, 0,
+            ])
+            .unwrap();
+        stream
+            .write_all(&[2, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 0])
+            .unwrap();
+        // Make sure the messages above have been handled before blitting.
+        stream.write_all(&[3, 0, 0, 0, 0, 0, 0, 1, 0, 1]).unwrap();
+        read_update(&mut stream, 16 + 2);
+        stream.write_all(&[3, 1, 0, 0, 0, 0, 0, 4, 0, 3]).unwrap();
+
+        let mut pb = PixelBufferTyped::<BGRA>::new(4, 3, &server).unwrap();
+        for pixel in &mut pb.row_mut(2).unwrap()[1..3] {
+            *pixel = BGRA::from_rgb(255, 0, 0);
+        }
+        pb.blit(&server).unwrap();
+
+        let update = read_update(&mut stream, 16 + 4 + 2 + 10);
+        assert_eq!(
+            &[0, 0, 0, 1, 0, 0, 0, 0, 0, 4, 0, 3, 0, 0, 0, 2][..],
+            &update[..16]
+        );
+        // One red subrectangle, on a black background.
+        assert_eq!(
+            &[0, 0, 0, 1, 0, 0, 0xf8, 0x00, 0, 1, 0, 2, 0, 2, 0, 1][..],
+            &update[16..]
+        );
+        assert_eq!(1, server.client_count());
+    }
+
+    #[test]
+    fn vnc_drop_closes_handshakes() {
+        let server = VncServer::bind("127.0.0.1:0", 4, 3, PixelBufferFormatType::BGRA).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        // Stall in the handshake, after the server sent its version.
+        stream.read_exact(&mut [0; 12]).unwrap();
+        drop(server);
+        assert_eq!(0, stream.read(&mut [0]).unwrap());
+    }
+}
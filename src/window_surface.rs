@@ -0,0 +1,137 @@
+use crate::{BlitError, BlitTarget, PixelBufferFormat, PixelBufferTyped, ResizeContents};
+
+/// A pixel buffer that follows the size of its window.
+///
+/// Calling [`resize`](Self::resize) only records the window's new size, so it can be called on
+/// every resize event without reallocating the buffer each time. The buffer catches up the next
+/// time it gets drawn into or blitted, keeping the pixels that are within both the old and the new
+/// size. That way, blits never present a buffer of an outdated size.
+pub struct WindowSurface<P: PixelBufferFormat> {
+    buffer: PixelBufferTyped<P>,
+    /// The size the buffer needs to be resized to, if it changed.
+    pending_size: Option<(u32, u32)>,
+}
+
+impl<P: PixelBufferFormat> WindowSurface<P> {
+    /// Creates a surface of the given size for `window`.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::new`].
+    pub fn new<H: BlitTarget>(
+        width: u32,
+        height: u32,
+        window: &H,
+    ) -> Result<WindowSurface<P>, BlitError> {
+        Ok(WindowSurface {
+            buffer: PixelBufferTyped::new(width, height, window)?,
+            pending_size: None,
+        })
+    }
+
+    /// The size of the window, as of the last call to [`resize`](Self::resize).
+    pub fn size(&self) -> (u32, u32) {
+        self.pending_size
+            .unwrap_or((self.buffer.width(), self.buffer.height()))
+    }
+
+    /// Records the window's new size. The buffer gets resized the next time it's needed.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.pending_size = match (width, height) == (self.buffer.width(), self.buffer.height()) {
+            true => None,
+            false => Some((width, height)),
+        };
+    }
+
+    /// Resizes the buffer to the window's size, if it changed.
+    fn apply_size(&mut self) -> Result<(), BlitError> {
+        if let Some((width, height)) = self.pending_size {
+            self.buffer
+                .resize(width, height, ResizeContents::Preserve)?;
+            self.pending_size = None;
+        }
+        Ok(())
+    }
+
+    /// The buffer, which may still have the window's previous size if it changed since the buffer
+    /// was last used.
+    pub fn buffer(&self) -> &PixelBufferTyped<P> {
+        &self.buffer
+    }
+
+    /// The buffer, resized to the window's size first if needed.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::resize`].
+    pub fn buffer_mut(&mut self) -> Result<&mut PixelBufferTyped<P>, BlitError> {
+        self.apply_size()?;
+        Ok(&mut self.buffer)
+    }
+
+    /// Blits the buffer onto `window`, resizing it to the window's size first if needed.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::resize`] and [`PixelBufferTyped::blit`].
+    pub fn blit<H: BlitTarget>(&mut self, window: &H) -> Result<(), BlitError> {
+        self.apply_size()?;
+        self.buffer.blit(window)
+    }
+
+    /// Blits the parts of the buffer that changed onto `window`, resizing it to the window's size
+    /// first if needed. See [`PixelBufferTyped::blit_damaged`].
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::resize`] and
+    /// [`PixelBufferTyped::blit_damaged`].
+    pub fn blit_damaged<H: BlitTarget>(&mut self, window: &H) -> Result<(), BlitError> {
+        self.apply_size()?;
+        self.buffer.blit_damaged(window)
+    }
+
+    /// Gives up the surface, returning its buffer resized to the window's size.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`PixelBufferTyped::resize`].
+    pub fn into_buffer(mut self) -> Result<PixelBufferTyped<P>, BlitError> {
+        self.apply_size()?;
+        Ok(self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeadlessWindow, PixelBufferFormatType, BGR};
+
+    #[test]
+    fn window_surface_resizes_lazily() {
+        let window = HeadlessWindow::new(3, 3, PixelBufferFormatType::BGR);
+        let mut surface = WindowSurface::<BGR>::new(2, 2, &window).unwrap();
+        let pixels = [BGR::new(1, 1, 1), BGR::new(2, 2, 2)];
+        surface
+            .buffer_mut()
+            .unwrap()
+            .row_mut(0)
+            .unwrap()
+            .copy_from_slice(&pixels);
+
+        surface.resize(1, 1);
+        surface.resize(3, 1);
+        assert_eq!((3, 1), surface.size());
+        assert_eq!(2, surface.buffer().width());
+
+        let buffer = surface.buffer_mut().unwrap();
+        assert_eq!((3, 1), (buffer.width(), buffer.height()));
+        assert_eq!(
+            &[pixels[0], pixels[1], BGR::new(0, 0, 0)][..],
+            buffer.row(0).unwrap()
+        );
+
+        surface.resize(1, 2);
+        surface.blit(&window).unwrap();
+        assert_eq!(
+            (1, 2),
+            (surface.buffer().width(), surface.buffer().height())
+        );
+        assert_eq!(&[1, 1, 1, 0, 0, 0][..], &window.row(0).unwrap()[..6]);
+    }
+}